@@ -0,0 +1,244 @@
+//! Batch files: a plain list of URLs, or CSV and JSON lists whose rows can override the
+//! download options. A file is checked completely before anything is queued.
+
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+
+use crate::convert::OUTPUT_FORMATS;
+use crate::download::DownloadOptions;
+use crate::playlist::Quality;
+use crate::util::{parse_section, parse_timestamp, section_from_bounds};
+
+/// One download from a batch file.
+#[derive(Clone, Debug)]
+pub struct BatchEntry {
+    pub url: String,
+    pub options: DownloadOptions,
+}
+
+/// Why one line (or JSON entry) of a batch file was rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchError {
+    /// `line 3`, or `entry 3` for JSON.
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BatchFormat {
+    /// One URL per line; blank lines and `#` comments are skipped.
+    Text,
+    /// A header row naming the columns (see [`COLUMNS`]), then one download per row.
+    Csv,
+    /// An array of URL strings or of objects with the [`COLUMNS`] as keys.
+    Json,
+}
+
+impl BatchFormat {
+    /// Guesses the format from the file extension, falling back to plain text.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("csv") => BatchFormat::Csv,
+            Some("json") => BatchFormat::Json,
+            _ => BatchFormat::Text,
+        }
+    }
+}
+
+/// The fields a CSV or JSON row can set. Only `url` is required; the time range is either
+/// `section` (`1:02:00-1:12:30`) or `start` and/or `end`.
+pub const COLUMNS: &[&str] = &["url", "format", "quality", "filename", "folder", "section", "start", "end"];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Row {
+    url: String,
+    format: Option<String>,
+    quality: Option<String>,
+    filename: Option<String>,
+    folder: Option<String>,
+    section: Option<String>,
+    start: Option<Time>,
+    end: Option<Time>,
+}
+
+/// A `start` or `end` time: seconds as a number (JSON `90`), or a timestamp such as `1:30`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Time {
+    Seconds(f64),
+    Text(String),
+}
+
+impl Time {
+    /// The time in seconds; `None` for an empty field.
+    fn seconds(self) -> Result<Option<f64>, String> {
+        match self {
+            Time::Seconds(seconds) if seconds >= 0.0 => Ok(Some(seconds)),
+            Time::Seconds(seconds) => Err(format!("invalid time '{seconds}'")),
+            Time::Text(text) => non_empty(Some(text))
+                .map(|t| parse_timestamp(&t).ok_or_else(|| format!("invalid time '{t}'")))
+                .transpose(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonRow {
+    Url(String),
+    Row(Row),
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+impl Row {
+    /// `defaults` with this row's overrides applied, or the first problem with it.
+    fn into_entry(
+        self,
+        defaults: &DownloadOptions,
+        check_url: &dyn Fn(&str) -> Result<(), String>,
+    ) -> Result<BatchEntry, String> {
+        let url = self.url.trim().to_string();
+        if url.is_empty() {
+            return Err("missing url".to_string());
+        }
+        check_url(&url)?;
+        let mut options = defaults.clone();
+        if let Some(format) = non_empty(self.format) {
+            let format = format.to_ascii_lowercase();
+            if !OUTPUT_FORMATS.contains(&format.as_str()) {
+                return Err(format!("unknown format '{format}' (expected one of {})", OUTPUT_FORMATS.join(", ")));
+            }
+            options.output_format = format;
+        }
+        if let Some(quality) = non_empty(self.quality) {
+            options.quality = Some(Quality::parse(&quality)?);
+        }
+        if let Some(filename) = non_empty(self.filename) {
+            // A name given with the extension of the format it is saved as.
+            let stem = match filename.rsplit_once('.') {
+                Some((stem, ext)) if OUTPUT_FORMATS.contains(&ext.to_ascii_lowercase().as_str()) => stem.to_string(),
+                _ => filename,
+            };
+            options.filename = Some(stem);
+        }
+        if let Some(folder) = non_empty(self.folder) {
+            options.download_folder = Some(folder);
+        }
+        let start = self.start.map(Time::seconds).transpose()?.flatten();
+        let end = self.end.map(Time::seconds).transpose()?.flatten();
+        if let Some(section) = non_empty(self.section) {
+            if start.is_some() || end.is_some() {
+                return Err("give either section or start/end, not both".to_string());
+            }
+            options.section = Some(parse_section(&section)?);
+        } else if start.is_some() || end.is_some() {
+            options.section = Some(section_from_bounds(start.unwrap_or(0.0), end)?);
+        }
+        Ok(BatchEntry { url, options })
+    }
+}
+
+/// Reads a whole batch file. Every entry starts from `defaults`, and `check_url` says
+/// whether (or why not) a URL can be downloaded. Returns either every entry or every
+/// problem, so nothing is queued from a file with mistakes in it.
+pub fn parse_batch(
+    text: &str,
+    format: BatchFormat,
+    defaults: &DownloadOptions,
+    check_url: &dyn Fn(&str) -> Result<(), String>,
+) -> Result<Vec<BatchEntry>, Vec<BatchError>> {
+    // Each row with where it came from, or why it could not be read.
+    let mut rows: Vec<(String, Result<Row, String>)> = Vec::new();
+    let line = |n: usize| format!("line {n}");
+    match format {
+        BatchFormat::Text => {
+            for (i, text) in text.lines().enumerate() {
+                // `#` only starts a comment at the start of a line or after a space, as
+                // URLs may contain it.
+                let url = text.split(" #").next().unwrap_or_default().trim();
+                if url.is_empty() || url.starts_with('#') {
+                    continue;
+                }
+                rows.push((line(i + 1), Ok(Row { url: url.to_string(), ..Default::default() })));
+            }
+        }
+        BatchFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .comment(Some(b'#'))
+                .flexible(true)
+                .trim(csv::Trim::All)
+                .from_reader(text.as_bytes());
+            let headers = match reader.headers() {
+                Ok(headers) => headers.clone(),
+                Err(e) => return Err(vec![BatchError { location: line(1), message: e.to_string() }]),
+            };
+            let headers = csv::StringRecord::from(headers.iter().map(str::to_ascii_lowercase).collect::<Vec<_>>());
+            let unknown: Vec<&str> = headers.iter().filter(|h| !COLUMNS.contains(h)).collect();
+            if !unknown.is_empty() || !headers.iter().any(|h| h == "url") {
+                let message = format!(
+                    "the header row must name a url column and may also have {}{}",
+                    COLUMNS[1..].join(", "),
+                    if unknown.is_empty() { String::new() } else { format!("; unknown: {}", unknown.join(", ")) }
+                );
+                return Err(vec![BatchError { location: line(1), message }]);
+            }
+            for record in reader.records() {
+                match record {
+                    Ok(record) => {
+                        let location = line(record.position().map_or(0, |p| p.line() as usize));
+                        rows.push((location, record.deserialize(Some(&headers)).map_err(|e| e.to_string())));
+                    }
+                    Err(e) => {
+                        let location = line(e.position().map_or(0, |p| p.line() as usize));
+                        rows.push((location, Err(e.to_string())));
+                    }
+                }
+            }
+        }
+        BatchFormat::Json => {
+            let values: Vec<serde_json::Value> = match serde_json::from_str(text) {
+                Ok(values) => values,
+                Err(e) => {
+                    let message = format!("expected a JSON array of URLs or objects: {e}");
+                    return Err(vec![BatchError { location: line(e.line()), message }]);
+                }
+            };
+            for (i, value) in values.into_iter().enumerate() {
+                let location = format!("entry {}", i + 1);
+                let row = match serde_json::from_value::<JsonRow>(value.clone()) {
+                    Ok(JsonRow::Url(url)) => Ok(Row { url, ..Default::default() }),
+                    Ok(JsonRow::Row(row)) => Ok(row),
+                    // Untagged enums hide why the object did not fit; ask again for the reason.
+                    Err(_) => Err(serde_json::from_value::<Row>(value)
+                        .err()
+                        .map_or_else(|| "expected a URL or an object".to_string(), |e| e.to_string())),
+                };
+                rows.push((location, row));
+            }
+        }
+    }
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+    for (location, row) in rows {
+        match row.and_then(|row| row.into_entry(defaults, check_url)) {
+            Ok(entry) => entries.push(entry),
+            Err(message) => errors.push(BatchError { location, message }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(entries)
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,6 @@
+//! Command-line downloader, built without the `gui` feature as well.
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(parti_vod_downloader::cli::run(args));
+}
@@ -0,0 +1,183 @@
+//! Chat replay export as JSON plus a WebVTT or ASS subtitle file.
+
+use chrono::DateTime;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::download::output_path;
+use crate::error::{Error, Result};
+use crate::http::{check_status, HttpClient};
+
+/// Subtitle format the chat replay is rendered to, next to the raw `_chat.json`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ChatFormat {
+    WebVtt,
+    Ass,
+}
+
+impl ChatFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ChatFormat::WebVtt => "vtt",
+            ChatFormat::Ass => "ass",
+        }
+    }
+}
+
+/// How long each chat line stays on screen.
+pub const CHAT_LINE_SECONDS: f64 = 6.0;
+
+#[derive(Clone, Debug)]
+pub struct ChatMessage {
+    /// Seconds since the start of the stream.
+    pub offset: f64,
+    pub author: String,
+    pub text: String,
+}
+
+/// Fetches the chat history of a VOD from `chat_url` (see [`Resolver::chat_url`]). A 404
+/// means the video has no chat replay; any other failure, including an answer that is not
+/// JSON, is an error.
+///
+/// [`Resolver::chat_url`]: crate::resolver::Resolver::chat_url
+pub fn fetch_chat(client: &HttpClient, chat_url: &str) -> Result<Option<Value>> {
+    debug!("Fetching chat: {chat_url}");
+    let resp = client.get(chat_url).send()?;
+    if resp.status().as_u16() == 404 {
+        debug!("No chat replay at {chat_url}");
+        return Ok(None);
+    }
+    Ok(Some(check_status(resp)?.json()?))
+}
+
+/// The list of messages in a chat API answer, which is either a bare array or an object
+/// wrapping one.
+fn message_list(json: &Value) -> Option<&Vec<Value>> {
+    json.as_array()
+        .or_else(|| ["messages", "chat", "comments", "data"].iter().find_map(|k| json.get(*k)?.as_array()))
+}
+
+fn first_str<'a>(value: &'a Value, keys: &[&str]) -> Option<&'a str> {
+    keys.iter().find_map(|k| value.get(*k).and_then(|v| v.as_str()))
+}
+
+/// Reads a timestamp that may be unix seconds, unix milliseconds or an RFC 3339 string.
+fn unix_seconds(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64().map(|t| if t > 1e11 { t / 1000.0 } else { t }),
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|dt| dt.timestamp_millis() as f64 / 1000.0)
+            .or_else(|| s.parse::<f64>().ok().map(|t| if t > 1e11 { t / 1000.0 } else { t })),
+        _ => None,
+    }
+}
+
+/// Extracts chat lines from the API response, which is either a bare array or an object
+/// wrapping one, and places them on the stream timeline relative to `stream_start` (unix secs).
+pub fn parse_chat_messages(json: &Value, stream_start: i64) -> Vec<ChatMessage> {
+    let Some(list) = message_list(json) else { return Vec::new() };
+    let mut messages: Vec<ChatMessage> = list
+        .iter()
+        .filter_map(|m| {
+            let text = first_str(m, &["message", "text", "content", "msg"])?.trim();
+            let author = first_str(m, &["username", "user_name", "display_name", "sender"])
+                .or_else(|| m.get("user").and_then(|u| first_str(u, &["username", "display_name", "name"])))
+                .unwrap_or("anonymous");
+            let offset = match ["offset", "video_offset", "relative_ts"].iter().find_map(|k| m.get(*k)?.as_f64()) {
+                Some(offset) => offset,
+                None => {
+                    let ts = ["timestamp", "ts", "created_at", "sent_at"].iter().find_map(|k| unix_seconds(m.get(*k)?))?;
+                    ts - stream_start as f64
+                }
+            };
+            Some(ChatMessage { offset, author: author.to_string(), text: text.to_string() })
+        })
+        .filter(|m| !m.text.is_empty())
+        .collect();
+    messages.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+    messages
+}
+
+pub(crate) fn vtt_timestamp(secs: f64) -> String {
+    let ms = (secs.max(0.0) * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02}.{:03}", ms / 3_600_000, (ms / 60_000) % 60, (ms / 1000) % 60, ms % 1000)
+}
+
+fn ass_timestamp(secs: f64) -> String {
+    let cs = (secs.max(0.0) * 100.0).round() as u64;
+    format!("{}:{:02}:{:02}.{:02}", cs / 360_000, (cs / 6000) % 60, (cs / 100) % 60, cs % 100)
+}
+
+/// Renders the messages falling into `[clip_start, clip_end)` with times relative to `clip_start`.
+pub fn render_chat(messages: &[ChatMessage], format: ChatFormat, clip_start: f64, clip_end: Option<f64>) -> String {
+    let mut out = match format {
+        ChatFormat::WebVtt => "WEBVTT\n\n".to_string(),
+        ChatFormat::Ass => "[Script Info]\nScriptType: v4.00+\nPlayResX: 1280\nPlayResY: 720\n\n\
+            [V4+ Styles]\n\
+            Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+            Style: Default,Arial,28,&H00FFFFFF,&H00FFFFFF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,0,7,20,20,20,1\n\n\
+            [Events]\n\
+            Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n"
+            .to_string(),
+    };
+    let in_clip = |m: &&ChatMessage| m.offset >= clip_start && clip_end.map(|end| m.offset < end).unwrap_or(true);
+    for m in messages.iter().filter(in_clip) {
+        let start = m.offset - clip_start;
+        let end = start + CHAT_LINE_SECONDS;
+        match format {
+            ChatFormat::WebVtt => {
+                let escape = |s: &str| s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('\n', " ");
+                out.push_str(&format!(
+                    "{} --> {}\n<v {}>{}\n\n",
+                    vtt_timestamp(start),
+                    vtt_timestamp(end),
+                    escape(&m.author),
+                    escape(&m.text)
+                ));
+            }
+            ChatFormat::Ass => {
+                let escape = |s: &str| s.replace('{', "(").replace('}', ")").replace('\n', "\\N");
+                out.push_str(&format!(
+                    "Dialogue: 0,{},{},Default,{},0,0,0,,{}: {}\n",
+                    ass_timestamp(start),
+                    ass_timestamp(end),
+                    escape(&m.author).replace(',', " "),
+                    escape(&m.author),
+                    escape(&m.text)
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// Saves `<stem>_chat.json` and the rendered subtitle file, returning how many lines were
+/// rendered, or `None` when the video has no chat replay or an empty one.
+pub fn save_chat(
+    client: &HttpClient,
+    chat_url: &str,
+    stream_start: i64,
+    format: ChatFormat,
+    clip: (f64, Option<f64>),
+    download_folder: Option<&str>,
+    stem: &str,
+) -> Result<Option<usize>> {
+    let Some(json) = fetch_chat(client, chat_url)? else { return Ok(None) };
+    let list = message_list(&json).ok_or_else(|| Error::InvalidChat("no list of messages in the answer".to_string()))?;
+    if list.is_empty() {
+        return Ok(None);
+    }
+    let messages = parse_chat_messages(&json, stream_start);
+    std::fs::write(
+        output_path(download_folder, &format!("{stem}_chat.json")),
+        serde_json::to_string_pretty(&json).map_err(std::io::Error::from)?,
+    )?;
+    std::fs::write(
+        output_path(download_folder, &format!("{stem}_chat.{}", format.extension())),
+        render_chat(&messages, format, clip.0, clip.1),
+    )?;
+    let (start, end) = clip;
+    Ok(Some(messages.iter().filter(|m| m.offset >= start && end.map(|e| m.offset < e).unwrap_or(true)).count()))
+}
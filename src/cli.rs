@@ -0,0 +1,406 @@
+//! The command-line front end, shared by `parti_vod_cli` and the desktop app when it is
+//! started with arguments.
+
+use log::LevelFilter;
+use std::io::{self, Write};
+
+use crate::batch::{parse_batch, BatchFormat};
+use crate::chat::ChatFormat;
+use crate::convert::OUTPUT_FORMATS;
+use crate::logging;
+use crate::download::{DownloadOptions, Downloader, Overwrite, SplitBy, MIN_RATE_LIMIT};
+use crate::http::HttpSettings;
+use crate::queue::{DownloadQueue, QueueItem, QueueState};
+use crate::playlist::Quality;
+use crate::resolver::{parse_input, HlsResolver, PartiResolver};
+use crate::subtitles::SubtitleFormat;
+use crate::util::{format_bytes, parse_rate_limit, parse_section, parse_size};
+
+/// Printed for `--help` and after a usage error.
+pub const CLI_USAGE: &str = "\
+Usage: parti_vod_cli [OPTIONS] <URL>...
+       parti_vod_cli [OPTIONS] --batch-file <FILE>
+
+Each URL can be a video page, a bare video ID, an .m3u8 playlist or, in builds with the
+channels feature, a channel's /videos page (which downloads all of its recordings).
+
+The parti_vod_downloader app takes the same options, and opens its window when started
+without any. Network settings saved in the app (proxy, credentials, headers, timeouts)
+apply here too unless overridden.
+
+Options:
+  -f, --format <FORMAT>    Output format: ts (default), mp4, mkv, mp3, wav, wmv, mov, webm
+      --hls                Treat every URL as an HLS playlist (master or media), even without .m3u8
+      --title <TITLE>      Name the output with this title instead of the video's
+      --date <YYYY-MM-DD>  Name the output with this date instead of the video's
+  -a, --batch-file <FILE>  Also download the videos listed in a .txt, .csv or .json file,
+                           whose rows may set their own format, quality, filename,
+                           folder and section
+      --quality <QUALITY>  best, worst or the tallest variant up to a height such as 720p
+                           (default: the playlist's first)
+  -o, --output <DIR>       Folder to save downloads into (default: current folder)
+      --overwrite <POLICY> When the output file exists: rename (default, adds _2, _3, ...),
+                           skip or overwrite
+      --ignore-free-space  Download even when the disk looks too full for the estimated size
+  -j, --jobs <N>           Download up to N videos at the same time (default: 1)
+  -r, --limit-rate <RATE>  Cap the total download speed, e.g. 5M or 500K bytes per second
+                           (at least 16K)
+      --section <RANGE>    Only download a time range, e.g. 1:02:00-1:12:30, 10:00- or -5:00
+      --precise            Re-encode the section so it is cut exactly at the given times
+      --split-duration <MINUTES>
+                           Split the output into parts of at most this many minutes
+      --split-size <SIZE>  Split the output into parts of at most this size, e.g. 2G or 700M
+      --chat               Also save the chat replay (JSON plus a subtitle file)
+      --chat-format <FMT>  Subtitle format for the chat replay: vtt (default) or ass
+      --subs               Save the playlist's subtitle tracks
+      --sub-format <FMT>   Subtitle file format: vtt (default) or srt
+      --embed-subs         Also mux the subtitles into mkv/mp4/mov/webm output
+  -v, --verbose            Print debug messages while downloading
+  -q, --quiet              Only print errors
+      --proxy <URL>        Connect through an http://, https://, socks5:// or socks5h:// proxy
+      --proxy-user <USER:PASSWORD>
+                           Log in to the proxy
+      --no-proxy <HOSTS>   Comma-separated hosts or domains to reach without the proxy
+      --cookies <FILE>     Send the cookies from a cookies.txt file, for members-only videos
+      --token <TOKEN>      Send an account's bearer token, for members-only videos
+      --user-agent <UA>    Send this User-Agent instead of the default
+  -H, --header <HEADER>    Send an extra 'Name: value' header; repeat for several
+      --referer <URL>      Send this Referer header
+      --origin <URL>       Send this Origin header
+      --connect-timeout <SECONDS>
+                           Give up connecting after this long (default: 15, 0 for never)
+      --read-timeout <SECONDS>
+                           Fail (and retry) a request that stalls this long (default: 30, 0 for never)
+      --api-base <URL>     Use another Parti API endpoint (default: https://api-backend.parti.com/parti_v2/)
+  -h, --help               Show this help";
+
+/// The URLs and options given on the command line.
+pub struct CliArgs {
+    pub urls: Vec<String>,
+    /// Files passed with `--batch-file`.
+    pub batch_files: Vec<String>,
+    pub options: DownloadOptions,
+    /// How many downloads run at the same time.
+    pub jobs: usize,
+    /// Bytes per second shared by all downloads.
+    pub rate_limit: Option<u64>,
+    /// Overrides [`crate::resolver::PARTI_API_BASE`].
+    pub api_base: Option<String>,
+    /// Treat every URL as an HLS playlist, see [`HlsResolver`].
+    pub hls: bool,
+    /// The network flags (`--proxy`, `--header`, ...) with their values, in the order given.
+    /// Parsing does not read the app's saved settings; see [`CliArgs::http_settings`].
+    pub network_flags: Vec<(String, String)>,
+    /// Log messages printed to stderr; `Error` also hides progress and success lines.
+    pub log_level: LevelFilter,
+}
+
+impl CliArgs {
+    /// The app's saved network settings with the network flags applied on top.
+    pub fn http_settings(&self) -> HttpSettings {
+        let mut http = HttpSettings::load();
+        for (flag, value) in &self.network_flags {
+            // Every flag was checked while parsing.
+            apply_network_flag(&mut http, flag, value.clone()).ok();
+        }
+        http
+    }
+}
+
+/// Parses the arguments after the program name, without reading any saved settings.
+/// `Err("")` means help was asked for.
+pub fn parse_cli_args(args: Vec<String>) -> Result<CliArgs, String> {
+    let mut urls = Vec::new();
+    let mut batch_files = Vec::new();
+    let mut options = DownloadOptions::default();
+    let mut jobs = 1;
+    let mut rate_limit = None;
+    let mut api_base = None;
+    let mut hls = false;
+    let mut log_level = LevelFilter::Warn;
+    let mut network_flags = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Accept both "--flag value" and "--flag=value".
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("Missing value for {name}"))
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Err(String::new()),
+            "-f" | "--format" => {
+                let format = value("--format")?.to_ascii_lowercase();
+                if !OUTPUT_FORMATS.contains(&format.as_str()) {
+                    return Err(format!("Unknown format '{format}' (expected one of {})", OUTPUT_FORMATS.join(", ")));
+                }
+                options.output_format = format;
+            }
+            "-a" | "--batch-file" => batch_files.push(value("--batch-file")?),
+            "--quality" => options.quality = Some(Quality::parse(&value("--quality")?)?),
+            "-o" | "--output" => options.download_folder = Some(value("--output")?),
+            "-j" | "--jobs" => {
+                let n = value("--jobs")?;
+                jobs = n.parse().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid job count '{n}'"))?;
+            }
+            "-r" | "--limit-rate" => {
+                let rate = value("--limit-rate")?;
+                let invalid = || format!("Invalid rate '{rate}' (the minimum is {}K)", MIN_RATE_LIMIT / 1024);
+                rate_limit = Some(parse_rate_limit(&rate).ok_or_else(invalid)?);
+            }
+            "-v" | "--verbose" => log_level = LevelFilter::Debug,
+            "-q" | "--quiet" => log_level = LevelFilter::Error,
+            "--api-base" => {
+                let base = value("--api-base")?;
+                url::Url::parse(&base).map_err(|e| format!("Invalid API base '{base}': {e}"))?;
+                api_base = Some(base);
+            }
+            _ if NETWORK_FLAGS.contains(&flag.as_str()) => {
+                let value = value(&flag)?;
+                // Checked now against the defaults; `run` applies them to the saved settings.
+                apply_network_flag(&mut HttpSettings::default(), &flag, value.clone())?;
+                network_flags.push((flag.clone(), value));
+            }
+            "--hls" => hls = true,
+            "--title" => options.title = Some(value("--title")?),
+            "--date" => {
+                let date = value("--date")?;
+                chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .map_err(|_| format!("Invalid date '{date}', expected YYYY-MM-DD"))?;
+                options.date = Some(date);
+            }
+            "--section" => options.section = Some(parse_section(&value("--section")?)?),
+            "--precise" => options.precise_trim = true,
+            "--overwrite" => options.overwrite = Overwrite::parse(&value("--overwrite")?)?,
+            "--ignore-free-space" => options.ignore_free_space = true,
+            "--chat" => {
+                options.chat.get_or_insert(ChatFormat::WebVtt);
+            }
+            "--chat-format" => {
+                options.chat = Some(match value("--chat-format")?.as_str() {
+                    "vtt" => ChatFormat::WebVtt,
+                    "ass" => ChatFormat::Ass,
+                    other => return Err(format!("Unknown chat format '{other}'")),
+                });
+            }
+            "--subs" => {
+                options.subtitles.get_or_insert(SubtitleFormat::WebVtt);
+            }
+            "--sub-format" => {
+                options.subtitles = Some(match value("--sub-format")?.as_str() {
+                    "vtt" => SubtitleFormat::WebVtt,
+                    "srt" => SubtitleFormat::Srt,
+                    other => return Err(format!("Unknown subtitle format '{other}'")),
+                });
+            }
+            "--embed-subs" => {
+                options.subtitles.get_or_insert(SubtitleFormat::WebVtt);
+                options.embed_subtitles = true;
+            }
+            "--split-duration" => {
+                let minutes = value("--split-duration")?;
+                match minutes.parse::<f64>() {
+                    Ok(m) if m > 0.0 => options.split = Some(SplitBy::Duration(m * 60.0)),
+                    _ => return Err(format!("Invalid part length '{minutes}'")),
+                }
+            }
+            "--split-size" => {
+                let size = value("--split-size")?;
+                let bytes = parse_size(&size).ok_or_else(|| format!("Invalid part size '{size}'"))?;
+                options.split = Some(SplitBy::Size(bytes));
+            }
+            _ if flag.starts_with('-') => return Err(format!("Unknown option '{flag}'")),
+            _ => urls.push(arg),
+        }
+    }
+    if urls.is_empty() && batch_files.is_empty() {
+        return Err("No video URL given".to_string());
+    }
+    if options.precise_trim && options.split.is_some() {
+        return Err("--precise cannot be combined with splitting into parts".to_string());
+    }
+    if hls && api_base.is_some() {
+        return Err("--hls does not use the Parti API, so --api-base has no effect".to_string());
+    }
+    Ok(CliArgs { urls, batch_files, options, jobs, rate_limit, api_base, hls, network_flags, log_level })
+}
+
+/// The flags [`apply_network_flag`] understands.
+const NETWORK_FLAGS: &[&str] = &[
+    "--proxy",
+    "--proxy-user",
+    "--no-proxy",
+    "--cookies",
+    "--token",
+    "--user-agent",
+    "-H",
+    "--header",
+    "--referer",
+    "--origin",
+    "--connect-timeout",
+    "--read-timeout",
+];
+
+/// Applies one network flag and its value to `http`.
+fn apply_network_flag(http: &mut HttpSettings, flag: &str, value: String) -> Result<(), String> {
+    match flag {
+        "--proxy" => http.proxy.url = value,
+        "--proxy-user" => {
+            let (name, password) = value.split_once(':').unwrap_or((&value, ""));
+            http.proxy.username = name.to_string();
+            http.proxy.password = password.to_string();
+        }
+        "--no-proxy" => http.proxy.no_proxy = value,
+        "--cookies" => http.auth.cookies_file = value,
+        "--token" => http.auth.bearer_token = value,
+        "--user-agent" => http.user_agent = value,
+        "-H" | "--header" => {
+            if !value.contains(':') {
+                return Err(format!("Header '{value}' must look like 'Name: value'"));
+            }
+            http.headers = format!("{}\n{value}", http.headers).trim_start().to_string();
+        }
+        "--referer" => http.referer = value,
+        "--origin" => http.origin = value,
+        "--connect-timeout" | "--read-timeout" => {
+            let seconds = value.parse().map_err(|_| format!("Invalid timeout '{value}'"))?;
+            match flag {
+                "--connect-timeout" => http.connect_timeout = seconds,
+                _ => http.read_timeout = seconds,
+            }
+        }
+        _ => unreachable!("not a network flag: {flag}"),
+    }
+    Ok(())
+}
+
+/// Headless mode: downloads every URL in turn, printing status lines to the terminal.
+pub fn run(args: Vec<String>) -> i32 {
+    let cli = match parse_cli_args(args) {
+        Ok(cli) => cli,
+        Err(e) if e.is_empty() => {
+            println!("{CLI_USAGE}");
+            return 0;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\n{CLI_USAGE}");
+            return 2;
+        }
+    };
+    logging::init("cli", cli.log_level);
+    let quiet = cli.log_level == LevelFilter::Error;
+    let mut downloader = match &cli.api_base {
+        _ if cli.hls => Downloader::with_resolver(HlsResolver),
+        Some(base) => Downloader::with_resolver(PartiResolver::with_api_base(base)),
+        None => Downloader::default(),
+    };
+    if let Err(e) = downloader.set_http(cli.http_settings()) {
+        eprintln!("error: {e}");
+        return 2;
+    }
+    let mut queue = DownloadQueue::with_downloader(cli.jobs, downloader);
+    queue.set_rate_limit(cli.rate_limit);
+    let mut inputs: Vec<(String, DownloadOptions)> =
+        cli.urls.iter().map(|url| (url.clone(), cli.options.clone())).collect();
+    // Batch files are checked in full before anything starts.
+    let check_url = |url: &str| {
+        if cli.hls {
+            Ok(())
+        } else {
+            parse_input(url).map(drop).map_err(|e| e.to_string())
+        }
+    };
+    for file in &cli.batch_files {
+        let entries = std::fs::read_to_string(file)
+            .map_err(|e| vec![e.to_string()])
+            .and_then(|text| {
+                parse_batch(&text, BatchFormat::from_path(file.as_ref()), &cli.options, &check_url)
+                    .map_err(|errors| errors.iter().map(|e| e.to_string()).collect())
+            });
+        match entries {
+            Ok(entries) => inputs.extend(entries.into_iter().map(|entry| (entry.url, entry.options))),
+            Err(errors) => {
+                eprintln!("error: {file}:\n  {}", errors.join("\n  "));
+                return 2;
+            }
+        }
+    }
+    let mut rejected = 0;
+    for (input, options) in &inputs {
+        match queue.downloader.expand(input) {
+            Ok(urls) => {
+                for url in urls {
+                    queue.add(url, options.clone());
+                }
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                rejected += 1;
+            }
+        }
+    }
+    let total = queue.items.len();
+    let mut reported = vec![false; total];
+    let mut last = String::new();
+    loop {
+        queue.tick();
+        for (i, item) in queue.items.iter().enumerate() {
+            if item.state.is_finished() && !reported[i] {
+                reported[i] = true;
+                let status = &item.progress.status;
+                if !quiet {
+                    print!("\r{:<79}\r", "");
+                    io::stdout().flush().ok();
+                }
+                if item.state == QueueState::Failed {
+                    eprintln!("[{}/{}] {}: {}", i + 1, total, item.url, status);
+                } else if !quiet {
+                    println!("[{}/{}] {}", i + 1, total, status);
+                }
+                last.clear();
+            }
+        }
+        if !queue.is_active() {
+            break;
+        }
+        // One line for whatever is running: the full status for a single download,
+        // a percentage per item when several run side by side.
+        let running: Vec<(usize, &QueueItem)> = queue
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.state == QueueState::Running)
+            .collect();
+        let line = match running.as_slice() {
+            [(_, item)] => match item.progress.transfer_summary() {
+                Some(summary) => format!("{} {}", item.progress.status, summary),
+                None => item.progress.status.clone(),
+            },
+            _ => running
+                .iter()
+                .map(|(i, item)| {
+                    let speed = item.progress.speed();
+                    if speed > 0.0 {
+                        format!("[{}] {:.0}% {}/s", i + 1, item.progress.fraction * 100.0, format_bytes(speed as u64))
+                    } else {
+                        format!("[{}] {:.0}%", i + 1, item.progress.fraction * 100.0)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  "),
+        };
+        if !quiet && line != last {
+            print!("\r{:<79.79}", line);
+            io::stdout().flush().ok();
+            last = line;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let failures = queue.items.iter().filter(|item| item.state == QueueState::Failed).count();
+    if failures + rejected > 0 { 1 } else { 0 }
+}
@@ -0,0 +1,236 @@
+//! Finding (or fetching) ffmpeg and converting downloads with it.
+
+use log::{debug, error, info};
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use which::which;
+
+use crate::error::{Error, Result};
+use crate::http::HttpClient;
+use crate::util::parse_timestamp;
+
+/// Finds ffmpeg on the `PATH`, or downloads a static build into `./ffmpeg-bin` once.
+/// The download goes through `client`, so it uses the same proxy as everything else.
+pub fn get_ffmpeg_path(client: &HttpClient) -> Result<PathBuf> {
+    find_or_fetch_ffmpeg(client).map_err(Error::FfmpegMissing)
+}
+
+fn find_or_fetch_ffmpeg(client: &HttpClient) -> std::result::Result<PathBuf, String> {
+    if let Ok(path) = which("ffmpeg") {
+        return Ok(path);
+    }
+    let local_path = if cfg!(windows) {
+        PathBuf::from("./ffmpeg-bin/ffmpeg.exe")
+    } else {
+        PathBuf::from("./ffmpeg-bin/ffmpeg")
+    };
+    if local_path.exists() {
+        return Ok(local_path);
+    }
+    info!("ffmpeg not found, downloading static binary...");
+    std::fs::create_dir_all("./ffmpeg-bin").map_err(|e| e.to_string())?;
+
+    #[cfg(target_os = "macos")]
+    let (url, bin_name) = (
+        "https://evermeet.cx/ffmpeg/ffmpeg-6.1.1.zip",
+        "ffmpeg"
+    );
+    #[cfg(target_os = "linux")]
+    let (url, bin_name) = (
+        "https://johnvansickle.com/ffmpeg/releases/ffmpeg-release-amd64-static.tar.xz",
+        "ffmpeg"
+    );
+    #[cfg(target_os = "windows")]
+    let (url, bin_name) = (
+        "https://www.gyan.dev/ffmpeg/builds/ffmpeg-release-essentials.zip",
+        "ffmpeg.exe"
+    );
+
+    let archive_path = "./ffmpeg-bin/ffmpeg_download";
+    let mut resp = client.get(url).send().and_then(|r| r.error_for_status()).map_err(|e| e.to_string())?;
+    let mut out = std::fs::File::create(archive_path).map_err(|e| e.to_string())?;
+    std::io::copy(&mut resp, &mut out).map_err(|e| e.to_string())?;
+
+    #[cfg(target_os = "macos")]
+    {
+        let file = std::fs::File::open(archive_path).map_err(|e| e.to_string())?;
+        let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            let outpath = file.sanitized_name();
+            if outpath.file_name().map(|n| n == bin_name).unwrap_or(false) {
+                let mut out_bin = std::fs::File::create("./ffmpeg-bin/ffmpeg").map_err(|e| e.to_string())?;
+                std::io::copy(&mut file, &mut out_bin).map_err(|e| e.to_string())?;
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions("./ffmpeg-bin/ffmpeg", std::fs::Permissions::from_mode(0o755)).ok();
+                break;
+            }
+        }
+    }
+    #[cfg(target_os = "windows")]
+    {
+        let file = std::fs::File::open(archive_path).map_err(|e| e.to_string())?;
+        let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            let outpath = file.sanitized_name();
+            if outpath.file_name().map(|n| n == bin_name).unwrap_or(false) {
+                let mut out_bin = std::fs::File::create("./ffmpeg-bin/ffmpeg.exe").map_err(|e| e.to_string())?;
+                std::io::copy(&mut file, &mut out_bin).map_err(|e| e.to_string())?;
+                break;
+            }
+        }
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let file = std::fs::File::open(archive_path).map_err(|e| e.to_string())?;
+        let decompressor = xz2::read::XzDecoder::new(file);
+        let mut archive = tar::Archive::new(decompressor);
+        for entry in archive.entries().map_err(|e| e.to_string())? {
+            let mut entry = entry.map_err(|e| e.to_string())?;
+            let path = entry.path().map_err(|e| e.to_string())?;
+            if path.file_name().map(|n| n == bin_name).unwrap_or(false) {
+                let mut out_bin = std::fs::File::create("./ffmpeg-bin/ffmpeg").map_err(|e| e.to_string())?;
+                std::io::copy(&mut entry, &mut out_bin).map_err(|e| e.to_string())?;
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions("./ffmpeg-bin/ffmpeg", std::fs::Permissions::from_mode(0o755)).ok();
+                break;
+            }
+        }
+    }
+
+    std::fs::remove_file(archive_path).ok();
+    if local_path.exists() {
+        Ok(local_path)
+    } else {
+        Err("Failed to download and unpack ffmpeg".to_string())
+    }
+}
+
+/// Every output format; `ts` is written as downloaded, the others go through ffmpeg.
+pub const OUTPUT_FORMATS: &[&str] = &["ts", "mp4", "mkv", "mp3", "wav", "wmv", "mov", "webm"];
+
+/// Cuts `duration` seconds starting `offset` seconds into the input (re-encodes for accuracy).
+#[derive(Clone, Copy, Debug)]
+pub struct Trim {
+    pub offset: f64,
+    pub duration: Option<f64>,
+}
+
+/// A subtitle file to mux into the converted output.
+#[derive(Clone, Debug)]
+pub struct SubtitleTrack {
+    pub path: PathBuf,
+    pub language: Option<String>,
+}
+
+/// Containers that can carry the subtitle tracks, with the codec to store them as.
+pub fn subtitle_codec(format: &str) -> Option<&'static str> {
+    match format {
+        "mkv" => Some("srt"),
+        "mp4" | "mov" => Some("mov_text"),
+        "webm" => Some("webvtt"),
+        _ => None,
+    }
+}
+
+/// Converts (and optionally trims, or muxes subtitles into) `input` to `output` with the
+/// ffmpeg from [`get_ffmpeg_path`].
+/// `on_progress` is called with 0.0 to 1.0 as ffmpeg works through the file.
+pub fn convert_with_ffmpeg(
+    ffmpeg_path: &Path,
+    input: &str,
+    output: &str,
+    format: &str,
+    trim: Option<Trim>,
+    subtitles: &[SubtitleTrack],
+    on_progress: &mut dyn FnMut(f32),
+) -> Result<()> {
+    debug!("Using ffmpeg at: {:?}", ffmpeg_path);
+    let mut cmd = std::process::Command::new(ffmpeg_path);
+    cmd.arg("-y");
+
+    // Seeking on the input only, so subtitle inputs (already aligned to the clip) keep their timing.
+    if let Some(trim) = trim {
+        cmd.arg("-ss").arg(format!("{:.3}", trim.offset));
+    }
+    cmd.arg("-i").arg(input);
+    if let Some(duration) = trim.and_then(|t| t.duration) {
+        cmd.arg("-t").arg(format!("{:.3}", duration));
+    }
+
+    if let (Some(codec), false) = (subtitle_codec(format), subtitles.is_empty()) {
+        for track in subtitles {
+            cmd.arg("-i").arg(&track.path);
+        }
+        cmd.args(["-map", "0:v?", "-map", "0:a?"]);
+        for (i, track) in subtitles.iter().enumerate() {
+            cmd.arg("-map").arg(format!("{}:s", i + 1));
+            if let Some(language) = &track.language {
+                cmd.arg(format!("-metadata:s:s:{i}")).arg(format!("language={language}"));
+            }
+        }
+        cmd.args(["-c:s", codec]);
+    }
+
+    match format {
+        "mp3" => { cmd.args(["-vn", "-acodec", "libmp3lame"]); }
+        "wav" => { cmd.args(["-vn", "-acodec", "pcm_s16le"]); }
+        // ffmpeg would pick mpeg2video/mp2 for .ts, only hit when re-encoding a trimmed clip.
+        "ts" => { cmd.args(["-c:v", "libx264", "-c:a", "aac"]); }
+        _ => {}
+    }
+
+    cmd.arg(output);
+    cmd.args(["-progress", "pipe:1", "-nostats"]);
+
+    debug!("Running: {:?} {:?}", cmd.get_program(), cmd.get_args());
+
+    let mut child = cmd
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::FfmpegMissing(format!("Failed to run ffmpeg: {e}")))?;
+    // ffmpeg logs the input duration on stderr; read it on its own thread so neither pipe fills up.
+    let (duration_tx, duration_rx) = std::sync::mpsc::channel();
+    let stderr = child.stderr.take().unwrap();
+    let stderr_reader = std::thread::spawn(move || {
+        let mut log = String::new();
+        for line in io::BufReader::new(stderr).lines().map_while(std::result::Result::ok) {
+            if let Some(duration) = line.trim().strip_prefix("Duration: ").and_then(|d| d.split(',').next()) {
+                if let Some(seconds) = parse_timestamp(duration) {
+                    duration_tx.send(seconds).ok();
+                }
+            }
+            log.push_str(&line);
+            log.push('\n');
+        }
+        log
+    });
+
+    let mut duration = trim.and_then(|t| t.duration);
+    let stdout = child.stdout.take().unwrap();
+    for line in io::BufReader::new(stdout).lines().map_while(std::result::Result::ok) {
+        if duration.is_none() {
+            duration = duration_rx.try_recv().ok().map(|d| d - trim.map_or(0.0, |t| t.offset));
+        }
+        // Despite the name, out_time_ms is in microseconds like out_time_us.
+        let Some(micros) = line.strip_prefix("out_time_us=").or_else(|| line.strip_prefix("out_time_ms=")) else {
+            continue;
+        };
+        if let (Ok(micros), Some(duration)) = (micros.trim().parse::<f64>(), duration) {
+            if duration > 0.0 {
+                on_progress((micros / 1e6 / duration).clamp(0.0, 1.0) as f32);
+            }
+        }
+    }
+
+    let status = child.wait()?;
+    let log = stderr_reader.join().unwrap_or_default();
+    if !status.success() {
+        error!("ffmpeg stderr: {}", log);
+        return Err(Error::FfmpegFailed { stderr: log });
+    }
+    on_progress(1.0);
+    Ok(())
+}
@@ -0,0 +1,949 @@
+//! Fetching a VOD's segments and turning them into the requested output files.
+
+use log::{debug, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+use crate::chat::{save_chat, ChatFormat};
+use crate::error::{Error, Result};
+use crate::convert::{convert_with_ffmpeg, get_ffmpeg_path, SubtitleTrack, Trim};
+use crate::http::{build_client, check_status, http_client, HttpClient, HttpSettings};
+use crate::playlist::{
+    is_audio_only, parse_master_playlist, parse_media_playlist, pick_audio_playlist, pick_variant, select_segments,
+    Quality,
+};
+use crate::preview::estimate_size;
+use crate::resolver::{PartiResolver, Resolver, VideoInfo};
+use crate::subtitles::{download_subtitles, render_subtitles, SubtitleFormat};
+use crate::util::{format_bytes, format_timestamp, free_space, sanitize_filename, Section};
+
+/// Where to start a new output part. Cuts only ever happen between segments.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SplitBy {
+    /// Maximum part length in seconds.
+    Duration(f64),
+    /// Maximum part size in bytes.
+    Size(u64),
+}
+
+impl SplitBy {
+    /// Whether a part of `bytes` / `duration` would be over the limit.
+    pub fn exceeded_by(&self, bytes: u64, duration: f64) -> bool {
+        match *self {
+            SplitBy::Duration(max) => duration > max + 0.001,
+            SplitBy::Size(max) => bytes > max,
+        }
+    }
+}
+
+/// What to do when a download's output file is already there.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Overwrite {
+    /// Leave it and finish without downloading anything.
+    Skip,
+    /// Replace it.
+    Overwrite,
+    /// Name the new file with `_2`, `_3`, ... added.
+    #[default]
+    Rename,
+}
+
+impl Overwrite {
+    /// Parses `skip`, `overwrite` or `rename`.
+    pub fn parse(s: &str) -> std::result::Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "skip" => Ok(Overwrite::Skip),
+            "overwrite" => Ok(Overwrite::Overwrite),
+            "rename" => Ok(Overwrite::Rename),
+            other => Err(format!("Invalid overwrite policy '{other}', expected skip, overwrite or rename")),
+        }
+    }
+}
+
+/// How far a download got: enough to continue with the next segment after a pause,
+/// an app restart or a failure.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Segment count of the (clipped) playlist, to detect a playlist that changed since.
+    pub total_segments: usize,
+    pub next_segment: usize,
+    /// Raw files written so far; the last one is still being appended to.
+    pub parts: Vec<PathBuf>,
+    /// Length of the last part at the time of the checkpoint, anything after it is discarded.
+    pub file_len: u64,
+    pub part_bytes: u64,
+    pub part_duration: f64,
+    /// The output name chosen when the download started, so a resumed download is not
+    /// renamed again because of its own files.
+    #[serde(default)]
+    pub stem: String,
+}
+
+impl Checkpoint {
+    pub fn is_usable(&self, total_segments: usize) -> bool {
+        let last_len = self
+            .parts
+            .last()
+            .and_then(|p| std::fs::metadata(p).ok())
+            .map(|m| m.len());
+        self.total_segments == total_segments
+            && self.next_segment <= total_segments
+            && self.parts.iter().all(|p| p.exists())
+            && last_len.map(|len| len >= self.file_len).unwrap_or(false)
+    }
+}
+
+/// A bandwidth cap shared by every download it is handed to. Clones share the same
+/// budget, and the limit can be changed (or lifted with `None`) while downloads run.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    /// Bytes per second, 0 for unlimited.
+    limit: Arc<std::sync::atomic::AtomicU64>,
+    /// Bytes that may still be read right now; negative when readers are ahead of the limit.
+    budget: Arc<Mutex<(f64, std::time::Instant)>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: Option<u64>) -> Self {
+        let limiter = Self {
+            limit: Arc::default(),
+            budget: Arc::new(Mutex::new((0.0, std::time::Instant::now()))),
+        };
+        limiter.set_limit(limit);
+        limiter
+    }
+
+    pub fn limit(&self) -> Option<u64> {
+        Some(self.limit.load(Ordering::Relaxed)).filter(|&l| l > 0)
+    }
+
+    pub fn set_limit(&self, limit: Option<u64>) {
+        self.limit.store(limit.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Accounts for `bytes` just read, sleeping long enough to stay under the limit. The wait is
+    /// slept in short slices, so a changed limit or a cancelled `control` ends it early.
+    pub fn take(&self, bytes: usize, control: &DownloadControl) {
+        let mut owed = bytes as f64;
+        while let Some(limit) = self.limit() {
+            let limit = limit as f64;
+            let wait = {
+                let mut budget = self.budget.lock().unwrap();
+                let (available, refilled) = &mut *budget;
+                // At most one second worth of bytes can be saved up for a burst.
+                *available = (*available + refilled.elapsed().as_secs_f64() * limit).min(limit);
+                *refilled = std::time::Instant::now();
+                *available -= std::mem::take(&mut owed);
+                (-*available / limit).max(0.0)
+            };
+            if wait == 0.0 || control.is_cancelled() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_secs_f64(wait).min(LIMIT_SLICE));
+        }
+    }
+}
+
+/// The longest [`RateLimiter::take`] sleeps before looking at the limit and the cancel flag again.
+const LIMIT_SLICE: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// How much of a segment is read at a time, and so accounted to the [`RateLimiter`] at once.
+const READ_CHUNK: usize = 16 * 1024;
+
+/// The lowest speed limit the CLI and the app accept: one read chunk per second. Anything
+/// slower would mostly leave downloads waiting on the limiter.
+pub const MIN_RATE_LIMIT: u64 = READ_CHUNK as u64;
+
+/// How often a playlist or segment request that failed with a retryable error (see
+/// [`Error::is_retryable`]) is tried again, waiting [`RETRY_DELAY`] before the first retry
+/// and twice as long before each next one.
+const RETRIES: u32 = 3;
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Runs `attempt` until it succeeds, fails for good or runs out of [`RETRIES`]. A
+/// cancelled download is not retried.
+fn retrying<T>(control: &DownloadControl, mut attempt: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut delay = RETRY_DELAY;
+    for _ in 0..RETRIES {
+        match attempt() {
+            Err(e) if e.is_retryable() && !control.is_cancelled() => {
+                warn!("{e}; retrying in {:.1}s", delay.as_secs_f64());
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    attempt()
+}
+
+/// The size a HEAD request for `url` reports, if the server gives one.
+fn content_length(client: &HttpClient, url: &str) -> Option<u64> {
+    let resp = client.head(url).send().ok()?.error_for_status().ok()?;
+    resp.headers().get(reqwest::header::CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+/// Reads a response body in small chunks so the limiter can pace it, giving up with
+/// [`Error::Aborted`] once `control` is cancelled.
+fn fetch_limited(client: &HttpClient, url: &str, limiter: &RateLimiter, control: &DownloadControl) -> Result<Vec<u8>> {
+    let mut resp = client.get(url).send()?.error_for_status()?;
+    let mut data = Vec::with_capacity(resp.content_length().unwrap_or(0) as usize);
+    let mut chunk = [0u8; READ_CHUNK];
+    loop {
+        let n = io::Read::read(&mut resp, &mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        limiter.take(n, control);
+        if control.is_cancelled() {
+            return Err(Error::Aborted);
+        }
+        data.extend_from_slice(&chunk[..n]);
+    }
+    Ok(data)
+}
+
+/// Lets the owner of a download cancel or pause it, and carries its [`Checkpoint`].
+/// Clones share the same state, so the owner keeps a clone and hands another to the
+/// download thread.
+#[derive(Clone, Debug, Default)]
+pub struct DownloadControl {
+    cancelled: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    pub(crate) checkpoint: Arc<Mutex<Option<Checkpoint>>>,
+}
+
+impl DownloadControl {
+    pub fn with_checkpoint(checkpoint: Option<Checkpoint>) -> Self {
+        let control = Self::default();
+        *control.checkpoint.lock().unwrap() = checkpoint;
+        control
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Stops fetching after the current segment, keeping the output open.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Clears the cancel and pause flags before a (re)start, keeping the checkpoint.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        self.checkpoint.lock().unwrap().clone()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadOptions {
+    pub output_format: String,
+    pub download_folder: Option<String>,
+    /// Only download the segments covering this range.
+    pub section: Option<Section>,
+    /// Re-encode the clip with ffmpeg so it starts/ends exactly on the section bounds
+    /// instead of on the surrounding segment boundaries. Ignored when splitting.
+    pub precise_trim: bool,
+    /// Write several `_partNN` files instead of one.
+    pub split: Option<SplitBy>,
+    /// Also save the chat replay, rendered to this subtitle format.
+    pub chat: Option<ChatFormat>,
+    /// Save the playlist's subtitle tracks as `<name>.<language>.<ext>`.
+    pub subtitles: Option<SubtitleFormat>,
+    /// Mux the subtitle tracks into mkv/mp4/mov/webm outputs (not when splitting).
+    pub embed_subtitles: bool,
+    /// Which variant to download; `None` takes the playlist's first.
+    pub quality: Option<Quality>,
+    /// The output's file name without extension, instead of `<title>_<date>`.
+    pub filename: Option<String>,
+    /// Name the output with this title instead of the resolved one.
+    pub title: Option<String>,
+    /// Name the output with this date (`YYYY-MM-DD`) instead of the resolved one.
+    pub date: Option<String>,
+    /// The video as already resolved for `url`, e.g. by [`Downloader::preview`], so the API
+    /// is not asked again. Not saved with the queue.
+    #[serde(skip)]
+    pub resolved: Option<VideoInfo>,
+    /// What to do when the output file already exists.
+    pub overwrite: Overwrite,
+    /// Download even when the estimated size is more than the free space, with a warning.
+    pub ignore_free_space: bool,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            output_format: "ts".to_string(),
+            download_folder: None,
+            section: None,
+            precise_trim: false,
+            split: None,
+            chat: None,
+            subtitles: None,
+            embed_subtitles: false,
+            quality: None,
+            filename: None,
+            title: None,
+            date: None,
+            resolved: None,
+            overwrite: Overwrite::default(),
+            ignore_free_space: false,
+        }
+    }
+}
+
+/// Everything a running download reports, in order. A download run by [`spawn_download`]
+/// always ends with exactly one of `Finished`, `Failed` or `Aborted`.
+#[derive(Clone, Debug)]
+pub enum DownloadEvent {
+    Resolved(VideoInfo),
+    /// A step without progress of its own ("Fetching playlist...", "Paused", ...).
+    Status(String),
+    /// Segment `index` (0-based) of `total` has been written. `duration` is its `#EXTINF`
+    /// length and `remaining` the seconds of video still to fetch after it.
+    SegmentDone { index: usize, total: usize, bytes: u64, duration: f64, remaining: f64 },
+    /// ffmpeg conversion progress, 0.0 to 1.0.
+    Converting { pct: f32 },
+    Finished { paths: Vec<PathBuf>, notes: Vec<String> },
+    Failed { error: Arc<Error> },
+    Aborted,
+}
+
+/// How far back [`DownloadProgress::speed`] looks.
+pub const SPEED_WINDOW: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The state of one download as seen by its owner, folded from its [`DownloadEvent`]s.
+#[derive(Clone, Debug, Default)]
+pub struct DownloadProgress {
+    pub info: Option<VideoInfo>,
+    pub status: String,
+    /// Overall progress, 0.0 to 1.0.
+    pub fraction: f32,
+    /// Segment bytes written so far.
+    pub bytes: u64,
+    /// Seconds of video behind `bytes`, to estimate the size of what is left.
+    media_seconds: f64,
+    /// Seconds of video still to download.
+    remaining_seconds: f64,
+    /// When each recent segment arrived and its size, for the rolling throughput.
+    recent: std::collections::VecDeque<(std::time::Instant, u64)>,
+    /// Set by the final event.
+    pub outcome: Option<DownloadEvent>,
+}
+
+impl DownloadProgress {
+    pub fn with_status(status: &str) -> Self {
+        Self { status: status.to_string(), ..Default::default() }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    pub fn apply(&mut self, event: DownloadEvent) {
+        match &event {
+            DownloadEvent::Resolved(info) => {
+                self.recent.push_back((std::time::Instant::now(), 0));
+                self.status = format!("Fetching playlist for '{}'", info.title);
+                self.info = Some(info.clone());
+            }
+            DownloadEvent::Status(status) => self.status = status.clone(),
+            DownloadEvent::SegmentDone { index, total, bytes, duration, remaining } => {
+                self.bytes += bytes;
+                self.media_seconds += duration;
+                self.remaining_seconds = *remaining;
+                let now = std::time::Instant::now();
+                self.recent.push_back((now, *bytes));
+                // Keep one sample from before the window so it has a start even when
+                // segments take longer than the window to arrive.
+                while self.recent.get(1).is_some_and(|(at, _)| now - *at > SPEED_WINDOW) {
+                    self.recent.pop_front();
+                }
+                self.fraction = (index + 1) as f32 / *total as f32;
+                self.status = format!("Downloading segment {}/{}...", index + 1, total);
+            }
+            DownloadEvent::Converting { pct } => {
+                self.recent.clear();
+                self.fraction = *pct;
+                self.status = format!("Converting... {:.0}%", pct * 100.0);
+            }
+            DownloadEvent::Finished { paths, notes } => {
+                self.fraction = 1.0;
+                self.status = saved_message(paths);
+                if !notes.is_empty() {
+                    self.status = format!("{} ({})", self.status, notes.join(", "));
+                }
+            }
+            DownloadEvent::Failed { error } => {
+                self.fraction = 1.0;
+                self.status = format!("Error: {}", error);
+            }
+            DownloadEvent::Aborted => {
+                self.fraction = 1.0;
+                self.status = "Aborted by user.".to_string();
+            }
+        }
+        if matches!(
+            event,
+            DownloadEvent::Finished { .. } | DownloadEvent::Failed { .. } | DownloadEvent::Aborted
+        ) {
+            self.outcome = Some(event);
+        }
+    }
+
+    /// Bytes per second over roughly the last [`SPEED_WINDOW`], zero while paused or converting.
+    pub fn speed(&self) -> f64 {
+        let (Some((first, _)), Some((last, _))) = (self.recent.front(), self.recent.back()) else {
+            return 0.0;
+        };
+        if last.elapsed() > SPEED_WINDOW {
+            return 0.0;
+        }
+        // The first sample only marks when the window started.
+        let bytes: u64 = self.recent.iter().skip(1).map(|(_, b)| b).sum();
+        let elapsed = first.elapsed().as_secs_f64();
+        if elapsed < 0.5 {
+            return 0.0;
+        }
+        bytes as f64 / elapsed
+    }
+
+    /// Time left for the remaining segments, sized after the ones downloaded so far.
+    pub fn eta(&self) -> Option<f64> {
+        let speed = self.speed();
+        if speed <= 0.0 || self.media_seconds <= 0.0 {
+            return None;
+        }
+        let bytes_per_second_of_video = self.bytes as f64 / self.media_seconds;
+        Some(self.remaining_seconds * bytes_per_second_of_video / speed)
+    }
+
+    /// `12.3 MB · 2.1 MB/s · ETA 0:03:12`, or `None` before the first segment.
+    pub fn transfer_summary(&self) -> Option<String> {
+        if self.bytes == 0 || self.is_finished() {
+            return None;
+        }
+        let mut summary = format_bytes(self.bytes);
+        let speed = self.speed();
+        if speed > 0.0 {
+            summary.push_str(&format!(" · {}/s", format_bytes(speed as u64)));
+        }
+        if let Some(eta) = self.eta() {
+            summary.push_str(&format!(" · ETA {}", format_timestamp(eta)));
+        }
+        Some(summary)
+    }
+
+    /// Applies every event received so far.
+    pub fn drain(&mut self, events: &Receiver<DownloadEvent>) {
+        while let Ok(event) = events.try_recv() {
+            self.apply(event);
+        }
+    }
+}
+
+/// What all downloads share: the HTTP client, the speed limit and how URLs are resolved.
+/// Clones share the same limiter.
+#[derive(Clone)]
+pub struct Downloader {
+    pub client: HttpClient,
+    /// What `client` was built from, see [`Downloader::set_http`].
+    pub http: HttpSettings,
+    pub limiter: RateLimiter,
+    pub resolver: Arc<dyn Resolver>,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Self::with_resolver(PartiResolver::default())
+    }
+}
+
+impl Downloader {
+    pub fn with_resolver(resolver: impl Resolver + 'static) -> Self {
+        Self {
+            client: http_client(),
+            http: HttpSettings::default(),
+            limiter: RateLimiter::new(None),
+            resolver: Arc::new(resolver),
+        }
+    }
+
+    /// Switches to a client built from `http`, which sends the bearer token to the
+    /// resolver's own hosts. Downloads already running keep the old one.
+    pub fn set_http(&mut self, http: HttpSettings) -> Result<()> {
+        let mut client = build_client(&http)?;
+        for base_url in self.resolver.token_hosts() {
+            client.trust(&base_url);
+        }
+        self.client = client;
+        self.http = http;
+        Ok(())
+    }
+
+    /// Checks an input before it is queued and expands a channel into its videos, see
+    /// [`Resolver::expand`]. Blocks while a channel is looked up.
+    pub fn expand(&self, input: &str) -> Result<Vec<String>> {
+        self.resolver.expand(&self.client, input)
+    }
+
+    /// Runs [`Downloader::download`] on its own thread. The receiver gets every event,
+    /// ending with `Finished`, `Failed` or `Aborted`.
+    pub fn spawn(
+        &self,
+        url: String,
+        options: DownloadOptions,
+        control: DownloadControl,
+    ) -> (std::thread::JoinHandle<()>, Receiver<DownloadEvent>) {
+        let (events, receiver) = std::sync::mpsc::channel();
+        let downloader = self.clone();
+        let handle = std::thread::spawn(move || {
+            let event = match downloader.download(&url, &options, &control, &events) {
+                Ok(()) => return,
+                Err(Error::Aborted) => DownloadEvent::Aborted,
+                Err(e) => {
+                    info!("Download {url} failed: {e}");
+                    DownloadEvent::Failed { error: Arc::new(e) }
+                }
+            };
+            events.send(event).ok();
+        });
+        (handle, receiver)
+    }
+
+    /// Downloads one video with `options`, reporting progress on `events` until it ends
+    /// with `Finished`. Failures, including [`Error::Aborted`] when cancelled, are returned
+    /// rather than sent, see [`Downloader::spawn`].
+    pub fn download(
+        &self,
+        url: &str,
+        options: &DownloadOptions,
+        control: &DownloadControl,
+        events: &Sender<DownloadEvent>,
+    ) -> Result<()> {
+        let (client, limiter) = (&self.client, &self.limiter);
+        let emit = |event: DownloadEvent| {
+            events.send(event).ok();
+        };
+        info!("Downloading {url}");
+        let output_format = options.output_format.as_str();
+        let download_folder = options.download_folder.as_deref();
+        let mut info = match &options.resolved {
+            Some(info) => info.clone(),
+            None => self.resolver.resolve(client, url)?,
+        };
+        if let Some(title) = options.title.clone() {
+            info.title = title;
+        }
+        if let Some(date) = options.date.clone() {
+            info.date = date;
+        }
+        emit(DownloadEvent::Resolved(info.clone()));
+        let title = &info.title;
+        let playback_url = &info.playlist_url;
+        let trim_clip = options.precise_trim && options.section.is_some() && options.split.is_none();
+        let name = match &options.filename {
+            Some(name) => sanitize_filename(name),
+            None => format!("{}_{}", sanitize_filename(title), info.date),
+        };
+        let part_stem = |stem: &str, part: usize| match options.split {
+            Some(_) => format!("{stem}_part{part:02}"),
+            None => stem.to_string(),
+        };
+        // A precisely trimmed .ts is re-encoded into the final name, so keep the raw download apart.
+        let raw_file = |stem: &str, part: usize| {
+            let filename = if trim_clip && output_format == "ts" {
+                format!("{}.untrimmed.ts", part_stem(stem, part))
+            } else {
+                format!("{}.ts", part_stem(stem, part))
+            };
+            output_path(download_folder, &filename)
+        };
+        let output_file = |stem: &str, part: usize| {
+            output_path(download_folder, &format!("{}.{output_format}", part_stem(stem, part)))
+        };
+        // The files a download under `stem` would write that are already there: outputs and
+        // the raw .ts they are converted from, of every part when splitting.
+        let existing_files = |stem: &str| -> Vec<PathBuf> {
+            let last_part = if options.split.is_some() { usize::MAX } else { 1 };
+            let mut files: Vec<PathBuf> = (1..=last_part)
+                .map(|part| [output_file(stem, part), raw_file(stem, part)])
+                .take_while(|files| files.iter().any(|file| file.exists()))
+                .flatten()
+                .filter(|file| file.exists())
+                .collect();
+            // A .ts output is its own raw file.
+            files.dedup();
+            files
+        };
+        let existing = |stem: &str| existing_files(stem).into_iter().next();
+        let stem = match control.checkpoint() {
+            Some(checkpoint) if !checkpoint.stem.is_empty() => checkpoint.stem,
+            Some(_) => name,
+            None => match (existing(&name), options.overwrite) {
+                (None, _) => name,
+                (Some(_), Overwrite::Overwrite) => {
+                    // A download with fewer parts would otherwise leave the old higher parts behind.
+                    for file in existing_files(&name) {
+                        debug!("Overwriting {}", file.display());
+                        std::fs::remove_file(&file)?;
+                    }
+                    name
+                }
+                (Some(_), Overwrite::Rename) => (2..)
+                    .map(|n| format!("{name}_{n}"))
+                    .find(|stem| existing(stem).is_none())
+                    .unwrap(),
+                (Some(existing), Overwrite::Skip) => {
+                    info!("{url}: {} already exists, skipping", existing.display());
+                    emit(DownloadEvent::Finished {
+                        paths: vec![existing],
+                        notes: vec!["already downloaded, skipped".to_string()],
+                    });
+                    return Ok(());
+                }
+            },
+        };
+        let raw_path = |part: usize| raw_file(&stem, part);
+
+        debug!("Fetching master playlist: {playback_url}");
+        let playlist = retrying(control, || Ok(check_status(client.get(playback_url).send()?)?.text()?))?;
+        let master = parse_master_playlist(&playlist, &Url::parse(playback_url)?)?;
+        debug!(
+            "Master playlist: {} variants, {} renditions",
+            master.variants.len(),
+            master.renditions.len()
+        );
+
+        let (variant_url, bandwidth) = if is_audio_only(output_format) {
+            // No need to pull the video track only for ffmpeg to drop it again.
+            match pick_audio_playlist(&master) {
+                Some(url) => {
+                    debug!("Audio-only output, using playlist: {url}");
+                    // An audio rendition has no bandwidth of its own; the video variants' would
+                    // overstate it, so the size is estimated from a segment instead.
+                    (url, master.variants.iter().find(|v| v.url == url).and_then(|v| v.bandwidth))
+                }
+                None => (playback_url.as_str(), None),
+            }
+        } else {
+            match pick_variant(&master, options.quality) {
+                Some(variant) => (variant.url.as_str(), variant.bandwidth),
+                None => (playback_url.as_str(), None),
+            }
+        };
+        emit(DownloadEvent::Status(format!("Fetching segments for '{}'", title)));
+        debug!("Fetching variant playlist: {variant_url}");
+        let text = retrying(control, || {
+            let resp = client.get(variant_url).send()?;
+            debug!("Variant playlist HTTP status: {}", resp.status());
+            let status = resp.status();
+            if matches!(status.as_u16(), 401 | 403) {
+                return Err(Error::AuthRequired { status: status.as_u16() });
+            }
+            if status.as_u16() == 429 || status.is_server_error() {
+                resp.error_for_status_ref()?;
+            }
+            // Any other error status counts as a missing playlist.
+            Ok(if status.is_success() { resp.text()? } else { String::new() })
+        })?;
+        trace!("Variant playlist:\n{text}");
+        if text.trim().is_empty() {
+            return Err(Error::EmptyVariant);
+        }
+        let variant_playlist = text;
+
+        let base = Url::parse(variant_url)?;
+        let media = parse_media_playlist(&variant_playlist, &base)?;
+        let mut segments = media.segments;
+
+        let mut trim = None;
+        // The part of the VOD timeline that ends up in the output, used to align the chat.
+        let mut clip = (0.0, None);
+        if let Some(section) = &options.section {
+            let total = segments.len();
+            let (selected, clip_start) = select_segments(&segments, section);
+            if selected.is_empty() {
+                return Err(Error::SectionOutOfRange);
+            }
+            debug!(
+                "Section {:?} covers {}/{} segments starting at {:.3}s",
+                section,
+                selected.len(),
+                total,
+                clip_start
+            );
+            segments = selected;
+            if trim_clip {
+                trim = Some(Trim {
+                    offset: (section.start - clip_start).max(0.0),
+                    duration: section.end.map(|end| end - section.start),
+                });
+                clip = (section.start, section.end);
+            } else {
+                clip = (clip_start, Some(clip_start + segments.iter().map(|s| s.duration).sum::<f64>()));
+            }
+        }
+
+        emit(DownloadEvent::Status(match &options.section {
+            Some(section) => format!(
+                "Downloading {} segments for {}-{}...",
+                segments.len(),
+                format_timestamp(section.start),
+                section.end.map(format_timestamp).unwrap_or_else(|| "end".to_string())
+            ),
+            None => format!("Downloading {} segments...", segments.len()),
+        }));
+
+        let resume = control.checkpoint().filter(|c| c.is_usable(segments.len()));
+        let first_segment = resume.as_ref().map_or(0, |c| c.next_segment);
+        let folder = Path::new(download_folder.unwrap_or("."));
+        if let (Some(next), Some(available)) = (segments.get(first_segment), free_space(folder)) {
+            let seconds: f64 = segments[first_segment..].iter().map(|s| s.duration).sum();
+            // Audio renditions and plain media playlists advertise no bandwidth, so go by
+            // the size of the next segment instead.
+            let bandwidth = bandwidth.or_else(|| {
+                let bytes = content_length(client, &next.url)?;
+                (next.duration > 0.0).then(|| (bytes as f64 * 8.0 / next.duration) as u64)
+            });
+            match bandwidth {
+                Some(bandwidth) => {
+                    // Converting keeps the downloaded .ts next to the converted file.
+                    let copies = if output_format != "ts" || trim.is_some() { 2 } else { 1 };
+                    let needed = estimate_size(bandwidth, seconds).saturating_mul(copies);
+                    if available < needed {
+                        let error = Error::NotEnoughSpace { needed, available };
+                        if !options.ignore_free_space {
+                            return Err(error);
+                        }
+                        warn!("{url}: {error}");
+                        emit(DownloadEvent::Status(format!("{error}; downloading anyway")));
+                    }
+                }
+                None => {
+                    warn!("{url}: could not estimate the download size, not checking the free space");
+                    emit(DownloadEvent::Status(
+                        "Could not estimate the download size; free space not checked".to_string(),
+                    ));
+                }
+            }
+        }
+
+        let init = match &media.init_url {
+            Some(url) => retrying(control, || Ok(client.get(url).send()?.error_for_status()?.bytes()?.to_vec()))?,
+            None => Vec::new(),
+        };
+        let (mut parts, mut out, mut part_bytes, mut part_duration, first_segment) = match resume {
+            Some(checkpoint) => {
+                debug!(
+                    "Resuming at segment {}/{}",
+                    checkpoint.next_segment + 1,
+                    checkpoint.total_segments
+                );
+                let mut file = std::fs::OpenOptions::new().write(true).open(checkpoint.parts.last().unwrap())?;
+                file.set_len(checkpoint.file_len)?;
+                file.seek(io::SeekFrom::End(0))?;
+                (
+                    checkpoint.parts,
+                    BufWriter::new(file),
+                    checkpoint.part_bytes,
+                    checkpoint.part_duration,
+                    checkpoint.next_segment,
+                )
+            }
+            None => {
+                let parts = vec![raw_path(1)];
+                let mut out = BufWriter::new(File::create(&parts[0])?);
+                out.write_all(&init)?;
+                (parts, out, 0u64, 0.0, 0)
+            }
+        };
+
+        let mut remaining: f64 = segments.iter().skip(first_segment).map(|s| s.duration).sum();
+        let fetched = (|| {
+            for (i, segment) in segments.iter().enumerate().skip(first_segment) {
+                if control.is_paused() && !control.is_cancelled() {
+                    emit(DownloadEvent::Status(format!("Paused at segment {}/{}", i + 1, segments.len())));
+                    while control.is_paused() && !control.is_cancelled() {
+                        std::thread::sleep(std::time::Duration::from_millis(200));
+                    }
+                }
+                if control.is_cancelled() {
+                    return Err(Error::Aborted);
+                }
+                let data = retrying(control, || fetch_limited(client, &segment.url, limiter, control))
+                    .map_err(|e| match e {
+                        Error::Aborted => e,
+                        e => Error::SegmentFailed { index: i, source: Box::new(e) },
+                    })?;
+                if let Some(split) = &options.split {
+                    let next_bytes = part_bytes + data.len() as u64;
+                    if part_bytes > 0 && split.exceeded_by(next_bytes, part_duration + segment.duration) {
+                        out.flush()?;
+                        parts.push(raw_path(parts.len() + 1));
+                        out = BufWriter::new(File::create(parts.last().unwrap())?);
+                        out.write_all(&init)?;
+                        part_bytes = 0;
+                        part_duration = 0.0;
+                    }
+                }
+                out.write_all(&data)?;
+                out.flush()?;
+                part_bytes += data.len() as u64;
+                part_duration += segment.duration;
+                *control.checkpoint.lock().unwrap() = Some(Checkpoint {
+                    total_segments: segments.len(),
+                    next_segment: i + 1,
+                    parts: parts.clone(),
+                    file_len: init.len() as u64 + part_bytes,
+                    part_bytes,
+                    part_duration,
+                    stem: stem.clone(),
+                });
+                remaining = (remaining - segment.duration).max(0.0);
+                emit(DownloadEvent::SegmentDone {
+                    index: i,
+                    total: segments.len(),
+                    bytes: data.len() as u64,
+                    duration: segment.duration,
+                    remaining,
+                });
+            }
+            Ok(())
+        })();
+        if let Err(e) = fetched {
+            // Nothing of this download was kept, so leave no empty file for a retry to rename around.
+            if first_segment == 0 && part_bytes == 0 && parts.len() == 1 {
+                drop(out);
+                std::fs::remove_file(&parts[0]).ok();
+            }
+            return Err(e);
+        }
+        out.flush()?;
+        drop(out);
+        // Cancelled while the last segment was fetched; the checkpoint still allows a resume.
+        if control.is_cancelled() {
+            return Err(Error::Aborted);
+        }
+
+        let mut notes = Vec::new();
+        let mut subtitle_tracks: Vec<SubtitleTrack> = Vec::new();
+        if let Some(sub_format) = options.subtitles {
+            let mut seen = Vec::new();
+            for rendition in master.renditions.iter().filter(|r| r.kind == "SUBTITLES") {
+                let Some(sub_url) = rendition.url.as_deref() else { continue };
+                if seen.contains(&sub_url) {
+                    continue;
+                }
+                seen.push(sub_url);
+                let label = sanitize_filename(rendition.language.as_deref().unwrap_or(&rendition.name));
+                let label = if label.is_empty() { format!("sub{}", seen.len()) } else { label };
+                emit(DownloadEvent::Status(format!("Fetching subtitles ({label})...")));
+                match download_subtitles(client, sub_url, clip) {
+                    Ok(cues) => {
+                        let path = output_path(download_folder, &format!("{stem}.{label}.{}", sub_format.extension()));
+                        std::fs::write(&path, render_subtitles(&cues, sub_format))?;
+                        subtitle_tracks.push(SubtitleTrack { path, language: rendition.language.clone() });
+                    }
+                    Err(e) => warn!("Subtitles '{label}': {e}"),
+                }
+            }
+            notes.push(format!("{} subtitle tracks", subtitle_tracks.len()));
+        }
+        let embedded_subtitles = if options.embed_subtitles && options.split.is_none() {
+            subtitle_tracks.as_slice()
+        } else {
+            &[]
+        };
+
+        // Convert (and/or trim) if needed
+        if output_format != "ts" || trim.is_some() {
+            // The static build comes from a third-party host, which gets no Parti credentials.
+            let ffmpeg = get_ffmpeg_path(&build_client(&self.http.without_auth())?)?;
+            let mut converted = Vec::new();
+            for (i, raw) in parts.iter().enumerate() {
+                let out_path = output_file(&stem, i + 1);
+                let part_count = parts.len() as f32;
+                emit(DownloadEvent::Converting { pct: i as f32 / part_count });
+                convert_with_ffmpeg(
+                    &ffmpeg,
+                    &raw.to_string_lossy(),
+                    &out_path.to_string_lossy(),
+                    output_format,
+                    trim,
+                    embedded_subtitles,
+                    &mut |pct| emit(DownloadEvent::Converting { pct: (i as f32 + pct) / part_count }),
+                )?;
+                if output_format == "ts" {
+                    std::fs::remove_file(raw).ok();
+                }
+                converted.push(out_path);
+            }
+            parts = converted;
+        }
+
+        if let Some(chat_format) = options.chat {
+            emit(DownloadEvent::Status("Fetching chat replay...".to_string()));
+            let chat_url = self.resolver.chat_url(&info);
+            let saved = match &chat_url {
+                Some(chat_url) => save_chat(client, chat_url, info.start_ts, chat_format, clip, download_folder, &stem),
+                None => Ok(None),
+            };
+            notes.push(match saved {
+                Ok(Some(count)) => format!("chat: {count} messages"),
+                Ok(None) => "no chat replay available".to_string(),
+                Err(e) => {
+                    warn!("Chat export: {e}");
+                    format!("chat export failed: {e}")
+                }
+            });
+        }
+
+        info!("{url}: {}", saved_message(&parts));
+        emit(DownloadEvent::Finished { paths: parts, notes });
+        Ok(())
+    }
+}
+
+pub(crate) fn output_path(download_folder: Option<&str>, filename: &str) -> PathBuf {
+    if let Some(folder) = download_folder {
+        Path::new(folder).join(filename)
+    } else {
+        PathBuf::from(filename)
+    }
+}
+
+fn saved_message(paths: &[PathBuf]) -> String {
+    match paths {
+        [path] => format!("Saved to {}", path.display()),
+        _ => format!(
+            "Saved {} parts to {}",
+            paths.len(),
+            paths[0].parent().map(|p| p.display().to_string()).filter(|p| !p.is_empty()).unwrap_or_else(|| ".".to_string())
+        ),
+    }
+}
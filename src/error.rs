@@ -0,0 +1,103 @@
+//! The error type returned by the library.
+
+use std::io;
+
+use crate::util::format_bytes;
+
+/// Why a download (or one of its steps) failed.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The input is not a URL (or ID) of a Parti video; holds the input and why.
+    #[error("Not a Parti video URL: {0}")]
+    InvalidUrl(String),
+    /// The input given to [`crate::HlsResolver`] is not an http(s) URL.
+    #[error("Not an HLS playlist URL: {0}")]
+    InvalidPlaylistUrl(String),
+    /// The Parti API answered with an error status.
+    #[error("The Parti API returned HTTP {status}")]
+    ApiHttp { status: u16 },
+    /// The API answer has none of the fields that point to the recording.
+    #[error("Could not find a video playlist field in API response")]
+    NoPlaylistField,
+    /// A playlist could not be understood.
+    #[error("Invalid playlist: {0}")]
+    InvalidPlaylist(String),
+    /// The chat replay answer is JSON, but not a list of messages.
+    #[error("Unexpected chat replay: {0}")]
+    InvalidChat(String),
+    /// The variant playlist came back empty.
+    #[error("Variant playlist is empty or not found")]
+    EmptyVariant,
+    /// The API or playlist host refused the request: the video is members-only and no (or
+    /// expired) credentials were sent.
+    #[error("Authentication required (HTTP {status}): this video needs a cookies file or token from a logged-in account")]
+    AuthRequired { status: u16 },
+    /// The requested section does not overlap the video.
+    #[error("The requested section is outside of the video")]
+    SectionOutOfRange,
+    /// The output folder's volume has less free space than the download is estimated to
+    /// need; see [`crate::DownloadOptions::ignore_free_space`].
+    #[error(
+        "Not enough free space: the download needs about {}, but only {} is free",
+        format_bytes(*needed),
+        format_bytes(*available)
+    )]
+    NotEnoughSpace { needed: u64, available: u64 },
+    /// Fetching segment `index` (0-based) failed; the checkpoint still allows a resume.
+    #[error("Segment {} failed: {source}", index + 1)]
+    SegmentFailed {
+        index: usize,
+        #[source]
+        source: Box<Error>,
+    },
+    /// ffmpeg is not installed and could not be downloaded.
+    #[error("ffmpeg is not available: {0}")]
+    FfmpegMissing(String),
+    /// ffmpeg ran but failed; `stderr` is its full log, the message shows its last line.
+    #[error("ffmpeg failed: {}", stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("no output"))]
+    FfmpegFailed { stderr: String },
+    /// Network settings that cannot be used, such as a malformed proxy URL.
+    #[error("Invalid network settings: {0}")]
+    Settings(String),
+    /// A request failed before any answer (connection, TLS, timeout, bad body, ...).
+    #[error("Network error: {0}")]
+    Http(#[from] reqwest::Error),
+    /// Reading or writing a local file.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Cancelled by the user.
+    #[error("Aborted by user.")]
+    Aborted,
+}
+
+impl Error {
+    /// Whether trying again later may succeed: timeouts, failed connections, 429 and 5xx
+    /// answers. Anything else (bad input, a 404, a full disk, a broken ffmpeg) fails the same
+    /// way on every try.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::ApiHttp { status } => *status == 429 || *status >= 500,
+            Error::Http(e) => is_transient(e),
+            // A response body that stalls while being read comes back as an io error.
+            Error::Io(e) => {
+                e.kind() == io::ErrorKind::TimedOut
+                    || e.get_ref().and_then(|inner| inner.downcast_ref::<reqwest::Error>()).is_some_and(is_transient)
+            }
+            Error::SegmentFailed { source, .. } => source.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect() || e.status().is_some_and(|s| s.as_u16() == 429 || s.is_server_error())
+}
+
+impl From<url::ParseError> for Error {
+    fn from(e: url::ParseError) -> Self {
+        Error::InvalidPlaylist(e.to_string())
+    }
+}
+
+/// `Result` with this crate's [`Error`].
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,180 @@
+//! A persistent record of every download that ended, whether it finished, failed or was
+//! cancelled.
+
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::download::{DownloadEvent, DownloadOptions, DownloadProgress};
+use crate::error::Result;
+use crate::util::data_dir;
+
+/// How a download in the [`History`] ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HistoryResult {
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+impl std::fmt::Display for HistoryResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HistoryResult::Finished => "finished",
+            HistoryResult::Failed => "failed",
+            HistoryResult::Cancelled => "cancelled",
+        })
+    }
+}
+
+/// One download that ended.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub url: String,
+    /// Empty (like `title`) when the download failed before the video was resolved.
+    pub video_id: String,
+    pub title: String,
+    /// The files written, several when split; empty unless finished.
+    pub paths: Vec<PathBuf>,
+    pub format: String,
+    /// Bytes of the files written, or of the segments fetched when unfinished.
+    pub size: u64,
+    /// Unix time the download ended.
+    pub time: i64,
+    pub result: HistoryResult,
+    /// Why it failed.
+    pub error: Option<String>,
+    /// What it ran with, to download it again.
+    pub options: DownloadOptions,
+}
+
+impl HistoryEntry {
+    /// The record of a download that ended with `progress`, `None` while it is still
+    /// running.
+    pub fn new(url: &str, options: &DownloadOptions, progress: &DownloadProgress) -> Option<Self> {
+        let (result, paths, error) = match progress.outcome.as_ref()? {
+            DownloadEvent::Finished { paths, .. } => (HistoryResult::Finished, paths.clone(), None),
+            DownloadEvent::Failed { error } => (HistoryResult::Failed, Vec::new(), Some(error.to_string())),
+            _ => (HistoryResult::Cancelled, Vec::new(), None),
+        };
+        let written: u64 = paths.iter().filter_map(|p| std::fs::metadata(p).ok()).map(|m| m.len()).sum();
+        let info = progress.info.as_ref();
+        Some(Self {
+            url: url.to_string(),
+            video_id: info.map(|i| i.video_id.clone()).unwrap_or_default(),
+            title: info.map(|i| i.title.clone()).unwrap_or_default(),
+            paths,
+            format: options.output_format.clone(),
+            size: if written > 0 { written } else { progress.bytes },
+            time: chrono::Utc::now().timestamp(),
+            result,
+            error,
+            options: options.clone(),
+        })
+    }
+
+    /// Whether `query` appears (ignoring case) in the URL, video ID, title or a path.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        query.is_empty()
+            || [&self.url, &self.video_id, &self.title].iter().any(|field| field.to_lowercase().contains(&query))
+            || self.paths.iter().any(|p| p.to_string_lossy().to_lowercase().contains(&query))
+    }
+
+    /// The end time as local `YYYY-MM-DD HH:MM:SS`.
+    pub fn local_time(&self) -> String {
+        chrono::DateTime::from_timestamp(self.time, 0)
+            .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Every recorded download, oldest first. Saved after each change when opened from a
+/// file; the default keeps it in memory only.
+#[derive(Default)]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,
+    save_path: Option<PathBuf>,
+}
+
+impl History {
+    /// The history saved in the data dir.
+    pub fn load() -> Self {
+        Self::open(data_dir().join("history.json"))
+    }
+
+    /// The history saved at `path`, empty if there is none yet.
+    pub fn open(path: PathBuf) -> Self {
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self { entries, save_path: Some(path) }
+    }
+
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.entries.push(entry);
+        self.save();
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.entries.len() {
+            self.entries.remove(index);
+            self.save();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.save();
+    }
+
+    /// Indices of the entries matching `query` (see [`HistoryEntry::matches`]) and, if
+    /// given, `result`, newest first.
+    pub fn search(&self, query: &str, result: Option<HistoryResult>) -> Vec<usize> {
+        (0..self.entries.len())
+            .rev()
+            .filter(|&i| {
+                let entry = &self.entries[i];
+                result.is_none_or(|r| entry.result == r) && entry.matches(query)
+            })
+            .collect()
+    }
+
+    pub fn save(&self) {
+        let Some(path) = &self.save_path else { return };
+        let saved = serde_json::to_string_pretty(&self.entries)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(path, json));
+        if let Err(e) = saved {
+            error!("Saving history: {e}");
+        }
+    }
+}
+
+/// Writes `entries` as CSV with a header row: time, result, title, video ID, URL, format,
+/// size in bytes, the output paths separated by `;`, and the error.
+pub fn write_csv<'a>(entries: impl IntoIterator<Item = &'a HistoryEntry>, writer: impl Write) -> Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+    let to_io = |e: csv::Error| std::io::Error::other(e);
+    csv.write_record(["time", "result", "title", "video_id", "url", "format", "size", "paths", "error"])
+        .map_err(to_io)?;
+    for entry in entries {
+        let paths: Vec<String> = entry.paths.iter().map(|p| p.to_string_lossy().into_owned()).collect();
+        csv.write_record([
+            entry.local_time(),
+            entry.result.to_string(),
+            entry.title.clone(),
+            entry.video_id.clone(),
+            entry.url.clone(),
+            entry.format.clone(),
+            entry.size.to_string(),
+            paths.join(";"),
+            entry.error.clone().unwrap_or_default(),
+        ])
+        .map_err(to_io)?;
+    }
+    csv.flush()?;
+    Ok(())
+}
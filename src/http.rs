@@ -0,0 +1,318 @@
+//! The one place HTTP clients are built, so the API, playlist, segment and ffmpeg
+//! downloads all go through the same proxy, and credentials only go where they belong.
+
+use log::{debug, warn};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::cookie::Jar;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, ORIGIN, REFERER};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::util::data_dir;
+
+/// Sent unless [`HttpSettings::user_agent`] says otherwise; the Parti backend rejects some
+/// library defaults.
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (compatible; parti_video_dl/1.0)";
+
+/// Where requests are sent through. An empty `url` connects directly.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxySettings {
+    /// `http://`, `https://`, `socks5://` or `socks5h://` (resolve host names on the proxy)
+    /// URL, e.g. `socks5h://127.0.0.1:1080`.
+    pub url: String,
+    /// Hosts that bypass the proxy, comma-separated like `NO_PROXY`: host names (which also
+    /// cover their subdomains), `.domain` suffixes, IP addresses or CIDR ranges.
+    pub no_proxy: String,
+    /// Proxy credentials; left empty when the URL carries them or none are needed.
+    pub username: String,
+    pub password: String,
+}
+
+impl ProxySettings {
+    pub fn is_enabled(&self) -> bool {
+        !self.url.trim().is_empty()
+    }
+
+    fn to_proxy(&self) -> Result<reqwest::Proxy> {
+        let url = self.url.trim();
+        let scheme = url.split_once("://").map(|(scheme, _)| scheme.to_ascii_lowercase());
+        if !matches!(scheme.as_deref(), Some("http" | "https" | "socks5" | "socks5h")) {
+            return Err(Error::Settings(format!(
+                "Proxy '{url}' must start with http://, https://, socks5:// or socks5h://"
+            )));
+        }
+        let mut proxy = reqwest::Proxy::all(url).map_err(|e| Error::Settings(format!("Proxy '{url}': {e}")))?;
+        if !self.username.is_empty() {
+            proxy = proxy.basic_auth(&self.username, &self.password);
+        }
+        Ok(proxy.no_proxy(reqwest::NoProxy::from_string(&self.no_proxy)))
+    }
+}
+
+/// Credentials for members-only recordings, sent only to the sites they belong to: the
+/// cookies by their domain, the token to Parti's hosts (see [`HttpClient`]).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthSettings {
+    /// A `cookies.txt` (Netscape format) exported from a browser logged in to Parti.
+    pub cookies_file: String,
+    /// Sent as `Authorization: Bearer <token>`.
+    pub bearer_token: String,
+}
+
+impl AuthSettings {
+    pub fn is_empty(&self) -> bool {
+        self.cookies_file.trim().is_empty() && self.bearer_token.trim().is_empty()
+    }
+}
+
+/// Everything that shapes outgoing requests. The GUI saves it in the data dir; the CLI
+/// builds it from flags.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpSettings {
+    pub proxy: ProxySettings,
+    pub auth: AuthSettings,
+    /// Empty sends [`DEFAULT_USER_AGENT`].
+    pub user_agent: String,
+    /// Extra headers sent with every request, one `Name: value` per line.
+    pub headers: String,
+    /// `Referer` and `Origin` headers, for CDNs that check them; empty sends none.
+    pub referer: String,
+    pub origin: String,
+    /// Seconds to wait for a connection to open, 0 for no limit.
+    pub connect_timeout: u64,
+    /// Seconds a request may wait for the response or its next bytes before failing, 0 for
+    /// no limit. Playlist and segment requests that time out are retried a few times.
+    pub read_timeout: u64,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            proxy: ProxySettings::default(),
+            auth: AuthSettings::default(),
+            user_agent: String::new(),
+            headers: String::new(),
+            referer: String::new(),
+            origin: String::new(),
+            connect_timeout: 15,
+            read_timeout: 30,
+        }
+    }
+}
+
+impl HttpSettings {
+    /// The settings saved by [`HttpSettings::save`], or the defaults.
+    pub fn load() -> Self {
+        std::fs::read_to_string(data_dir().join("network.json"))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// The same settings without credentials, for requests to hosts other than Parti's.
+    pub fn without_auth(&self) -> Self {
+        Self { auth: AuthSettings::default(), ..self.clone() }
+    }
+
+    /// The `Name: value` lines of [`HttpSettings::headers`], then `Referer` and `Origin`.
+    fn header_map(&self) -> Result<HeaderMap> {
+        let mut map = HeaderMap::new();
+        for line in self.headers.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let invalid = || Error::Settings(format!("Header '{line}' must look like 'Name: value'"));
+            let (name, value) = line.split_once(':').ok_or_else(invalid)?;
+            let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| invalid())?;
+            map.append(name, HeaderValue::from_str(value.trim()).map_err(|_| invalid())?);
+        }
+        for (name, value) in [(REFERER, &self.referer), (ORIGIN, &self.origin)] {
+            let value = value.trim();
+            if !value.is_empty() {
+                let value = HeaderValue::from_str(value)
+                    .map_err(|_| Error::Settings(format!("{name} '{value}' contains invalid characters")))?;
+                map.insert(name, value);
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&data_dir().join("network.json"))
+    }
+
+    /// Writes the settings to `path`. They include the bearer token and proxy password, so
+    /// on Unix only the owner may read the file.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::from)?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        // The mode only applies to new files; a file saved by an older version may be wider.
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        file.write_all(json.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// What every request goes through. The bearer token is only added for `parti.com`, its
+/// subdomains and hosts added with [`HttpClient::trust`], so playlists, segments and
+/// thumbnails on other hosts never see it.
+#[derive(Clone, Debug)]
+pub struct HttpClient {
+    client: Client,
+    /// `client` plus the `Authorization` header; `None` without a token.
+    authorized: Option<Client>,
+    /// Hosts (with their subdomains) that get the token, and the port if it matters.
+    token_hosts: Vec<(String, Option<u16>)>,
+}
+
+impl HttpClient {
+    pub fn get(&self, url: impl AsRef<str>) -> RequestBuilder {
+        self.client_for(url.as_ref()).get(url.as_ref())
+    }
+
+    pub fn head(&self, url: impl AsRef<str>) -> RequestBuilder {
+        self.client_for(url.as_ref()).head(url.as_ref())
+    }
+
+    fn client_for(&self, url: &str) -> &Client {
+        match &self.authorized {
+            Some(authorized) if self.sends_token_to(url) => authorized,
+            _ => &self.client,
+        }
+    }
+
+    /// Also sends the token to the host and port of `base_url`, e.g. an API mirror.
+    pub fn trust(&mut self, base_url: &str) {
+        if let Ok(url) = url::Url::parse(base_url) {
+            if let Some(host) = url.host_str() {
+                self.token_hosts.push((host.to_ascii_lowercase(), url.port_or_known_default()));
+            }
+        }
+    }
+
+    pub fn sends_token_to(&self, url: &str) -> bool {
+        let Ok(url) = url::Url::parse(url) else { return false };
+        let Some(host) = url.host_str().map(str::to_ascii_lowercase) else { return false };
+        self.token_hosts.iter().any(|(trusted, port)| {
+            let host_matches = host == *trusted || host.ends_with(&format!(".{trusted}"));
+            host_matches && port.is_none_or(|port| url.port_or_known_default() == Some(port))
+        })
+    }
+}
+
+/// Builds a client for `settings`, failing on settings that cannot work (such as an
+/// unsupported proxy scheme or an unreadable cookies file) rather than silently going
+/// without them.
+pub fn build_client(settings: &HttpSettings) -> Result<HttpClient> {
+    let user_agent = match settings.user_agent.trim() {
+        "" => DEFAULT_USER_AGENT,
+        custom => custom,
+    };
+    let proxy = if settings.proxy.is_enabled() { Some(settings.proxy.to_proxy()?) } else { None };
+    let cookies_file = settings.auth.cookies_file.trim();
+    let jar = if cookies_file.is_empty() { None } else { Some(Arc::new(load_cookies(cookies_file)?)) };
+    let headers = settings.header_map()?;
+    // The client with and without the token differ only in their default headers.
+    let build = |headers: HeaderMap| -> Result<Client> {
+        let seconds = |s: u64| (s > 0).then(|| Duration::from_secs(s));
+        let mut builder = Client::builder()
+            .user_agent(user_agent)
+            .connect_timeout(seconds(settings.connect_timeout))
+            // The blocking client applies this to each wait: for the response, then per read.
+            .timeout(seconds(settings.read_timeout))
+            .default_headers(headers);
+        if let Some(proxy) = &proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        if let Some(jar) = &jar {
+            builder = builder.cookie_provider(jar.clone());
+        }
+        Ok(builder.build()?)
+    };
+    let token = settings.auth.bearer_token.trim();
+    let authorized = if token.is_empty() {
+        None
+    } else {
+        let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+            .map_err(|_| Error::Settings("The bearer token contains invalid characters".to_string()))?;
+        value.set_sensitive(true);
+        let mut headers = headers.clone();
+        headers.insert(AUTHORIZATION, value);
+        Some(build(headers)?)
+    };
+    Ok(HttpClient {
+        client: build(headers)?,
+        authorized,
+        token_hosts: vec![("parti.com".to_string(), None)],
+    })
+}
+
+/// Reads a Netscape `cookies.txt`: one cookie per line as `domain`, `include subdomains`,
+/// `path`, `secure`, `expires` (Unix time, 0 for session cookies), `name` and `value`,
+/// separated by tabs. Expired cookies are left out.
+pub fn load_cookies(path: &str) -> Result<Jar> {
+    let text = std::fs::read_to_string(path).map_err(|e| Error::Settings(format!("Cookies file '{path}': {e}")))?;
+    let now = chrono::Utc::now().timestamp();
+    let jar = Jar::default();
+    let mut count = 0;
+    for line in text.lines() {
+        // Browsers mark HttpOnly cookies with a prefix that otherwise looks like a comment.
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [domain, subdomains, path, secure, expires, name, value] = fields[..] else {
+            debug!("Skipping malformed cookies.txt line: {line}");
+            continue;
+        };
+        if expires.parse::<i64>().map(|t| t != 0 && t < now).unwrap_or(false) {
+            continue;
+        }
+        let host = domain.trim_start_matches('.');
+        let secure = secure.eq_ignore_ascii_case("TRUE");
+        let Ok(url) = url::Url::parse(&format!("{}://{host}{path}", if secure { "https" } else { "http" })) else {
+            continue;
+        };
+        let mut cookie = format!("{name}={value}; Path={path}");
+        if subdomains.eq_ignore_ascii_case("TRUE") {
+            cookie.push_str(&format!("; Domain={host}"));
+        }
+        if secure {
+            cookie.push_str("; Secure");
+        }
+        jar.add_cookie_str(&cookie, &url);
+        count += 1;
+    }
+    if count == 0 {
+        return Err(Error::Settings(format!("No cookies found in '{path}'; is it a Netscape-format cookies.txt?")));
+    }
+    debug!("Loaded {count} cookies from {path}");
+    Ok(jar)
+}
+
+/// Like `error_for_status`, but a 401 or 403 becomes [`Error::AuthRequired`].
+pub(crate) fn check_status(response: Response) -> Result<Response> {
+    match response.status().as_u16() {
+        status @ (401 | 403) => Err(Error::AuthRequired { status }),
+        _ => Ok(response.error_for_status()?),
+    }
+}
+
+/// A client with the default settings. A proxy from the environment (`HTTPS_PROXY`, ...)
+/// is still honored.
+pub fn http_client() -> HttpClient {
+    build_client(&HttpSettings::default()).unwrap_or_else(|e| {
+        warn!("Building HTTP client: {e}");
+        HttpClient { client: Client::new(), authorized: None, token_hosts: Vec::new() }
+    })
+}
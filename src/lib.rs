@@ -0,0 +1,56 @@
+//! Downloading Parti VODs: the engine behind the Parti VOD Downloader app.
+//!
+//! A download goes through four steps, each usable on its own:
+//!
+//! - [`resolver`] turns a video page URL into a [`VideoInfo`] with the recording's playlist,
+//!   behind the [`Resolver`] trait so other backends (or test servers) can be plugged in.
+//! - [`playlist`] parses HLS master and media playlists and picks the segments to fetch.
+//! - [`download`] fetches the segments into `.ts` files, reporting [`DownloadEvent`]s, and
+//!   runs the other steps: [`Downloader::download`] does everything for one URL.
+//! - [`convert`] turns the result into the requested format with ffmpeg.
+//!
+//! [`Downloader::preview`] shows what a URL holds before downloading it; [`DownloadQueue`]
+//! runs many downloads side by side; [`chat`] and [`subtitles`] export the extra tracks.
+//! Without the default `gui` feature, only this library and the command-line binary are
+//! built.
+//!
+//! ```no_run
+//! use parti_vod_downloader::{DownloadControl, DownloadOptions, Downloader};
+//!
+//! let (events, receiver) = std::sync::mpsc::channel();
+//! let options = DownloadOptions { output_format: "mp4".to_string(), ..Default::default() };
+//! Downloader::default().download("https://parti.com/video/12345", &options, &DownloadControl::default(), &events)?;
+//! for event in receiver.try_iter() {
+//!     println!("{event:?}");
+//! }
+//! # Ok::<(), parti_vod_downloader::Error>(())
+//! ```
+
+pub mod batch;
+pub mod chat;
+pub mod cli;
+pub mod convert;
+pub mod download;
+pub mod error;
+pub mod history;
+pub mod http;
+pub mod logging;
+pub mod playlist;
+pub mod preview;
+pub mod queue;
+pub mod resolver;
+pub mod subtitles;
+pub mod util;
+
+pub use convert::{convert_with_ffmpeg, get_ffmpeg_path};
+pub use download::{
+    DownloadControl, DownloadEvent, DownloadOptions, DownloadProgress, Downloader, Overwrite, RateLimiter, SplitBy,
+    MIN_RATE_LIMIT,
+};
+pub use error::{Error, Result};
+pub use history::{History, HistoryEntry, HistoryResult};
+pub use http::{build_client, http_client, AuthSettings, HttpClient, HttpSettings, ProxySettings};
+pub use preview::Preview;
+pub use queue::{DownloadQueue, QueueState};
+pub use resolver::{HlsResolver, PartiResolver, Resolver, VideoInfo};
+pub use util::{parse_rate_limit, parse_section, parse_size, sanitize_filename, Section};
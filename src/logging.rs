@@ -0,0 +1,159 @@
+//! Leveled logging for the binaries: to the console, to rotating files in the data dir and
+//! into a buffer the GUI shows in its log panel. The library itself only uses the `log`
+//! macros, so embedders can install any logger instead.
+
+use chrono::Local;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::util::data_dir;
+
+/// Entries kept in memory for the log panel.
+const BUFFER_ENTRIES: usize = 2000;
+/// A log file is rotated once it grows past this size.
+const MAX_FILE_BYTES: u64 = 2 << 20;
+/// Rotated files kept next to the current one (`.1.log` is the most recent).
+const KEPT_FILES: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    pub time: chrono::DateTime<Local>,
+    pub level: Level,
+    /// Module the entry came from, e.g. `parti_vod_downloader::download`.
+    pub target: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:<5} {}: {}",
+            self.time.format("%Y-%m-%d %H:%M:%S%.3f"),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+/// The most recent log entries, oldest first. Clones share the same buffer.
+#[derive(Clone, Debug, Default)]
+pub struct LogBuffer(Arc<Mutex<VecDeque<LogEntry>>>);
+
+impl LogBuffer {
+    pub fn entries(&self) -> Vec<LogEntry> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    fn push(&self, entry: LogEntry) {
+        let mut entries = self.0.lock().unwrap();
+        if entries.len() == BUFFER_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { path, file, len })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.len + line.len() as u64 > MAX_FILE_BYTES {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{line}")?;
+        self.len += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    /// `app.log` becomes `app.1.log`, `app.1.log` becomes `app.2.log`, and so on.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let numbered = |n: usize| self.path.with_extension(format!("{n}.log"));
+        for n in (1..KEPT_FILES).rev() {
+            std::fs::rename(numbered(n), numbered(n + 1)).ok();
+        }
+        std::fs::rename(&self.path, numbered(1))?;
+        *self = Self::open(self.path.clone())?;
+        Ok(())
+    }
+}
+
+struct Logger {
+    console: LevelFilter,
+    file: Option<Mutex<RotatingFile>>,
+    buffer: LogBuffer,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        // Dependencies (reqwest, eframe, ...) only get a say when something goes wrong.
+        if !record.target().starts_with("parti_vod") && record.level() > Level::Warn {
+            return;
+        }
+        let entry = LogEntry {
+            time: Local::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+        if record.level() <= self.console {
+            eprintln!("[{}] {}", entry.level, entry.message);
+        }
+        if let Some(file) = &self.file {
+            file.lock().unwrap().write_line(&entry.to_string()).ok();
+        }
+        self.buffer.push(entry);
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            file.lock().unwrap().file.flush().ok();
+        }
+    }
+}
+
+/// Installs the logger: entries at `console` level or above go to stderr, everything down
+/// to debug goes to `<data dir>/logs/<name>.log` and the returned buffer. Only the first
+/// call has an effect.
+pub fn init(name: &str, console: LevelFilter) -> LogBuffer {
+    let buffer = LogBuffer::default();
+    let dir = data_dir().join("logs");
+    std::fs::create_dir_all(&dir).ok();
+    let file = RotatingFile::open(dir.join(format!("{name}.log"))).ok().map(Mutex::new);
+    let logger = Logger { console, file, buffer: buffer.clone() };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(console.max(LevelFilter::Debug));
+    }
+    buffer
+}
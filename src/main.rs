@@ -1,533 +1,1265 @@
-use chrono::{NaiveDateTime, TimeZone, Utc};
 use eframe::{egui, App, Frame};
-use regex::Regex;
-use reqwest::blocking::Client;
-use serde_json::Value;
-use std::fs::File;
-use std::io::{self, BufRead, BufWriter};
-use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use parti_vod_downloader::batch::{parse_batch, BatchFormat};
+use parti_vod_downloader::chat::ChatFormat;
+use parti_vod_downloader::convert::OUTPUT_FORMATS;
+use parti_vod_downloader::playlist::Quality;
+use parti_vod_downloader::preview::Preview;
+use parti_vod_downloader::history::{write_csv, HistoryEntry, HistoryResult};
+use parti_vod_downloader::http::{HttpSettings, DEFAULT_USER_AGENT};
+use parti_vod_downloader::download::{
+    DownloadControl, DownloadEvent, DownloadOptions, DownloadProgress, Overwrite, SplitBy, MIN_RATE_LIMIT,
+};
+use parti_vod_downloader::queue::{DownloadQueue, QueueState};
+use parti_vod_downloader::resolver::{find_video_urls, parse_input, Target};
+use parti_vod_downloader::subtitles::SubtitleFormat;
+use parti_vod_downloader::util::{
+    format_bytes, format_timestamp, free_space, parse_rate_limit, parse_timestamp, section_from_bounds, Section,
+};
+use log::{warn, Level, LevelFilter};
+use parti_vod_downloader::cli;
+use parti_vod_downloader::logging::{self, LogBuffer, LogEntry};
 use std::sync::atomic::{AtomicBool, Ordering};
-use url::Url;
-use which::which;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 
 // --- NEW: For icon loading ---
 use egui::IconData;
-use image::GenericImageView;
 // -----------------------------
 
-fn sanitize_filename(s: &str) -> String {
-    let re = Regex::new(r"[^\w\d]+").unwrap();
-    let s = re.replace_all(s, "_");
-    let s = s.trim_matches('_');
-    let s = Regex::new(r"_+").unwrap().replace_all(&s, "_");
-    s.to_string()
+#[derive(Clone, Copy, PartialEq)]
+enum SplitKind {
+    Off,
+    Minutes,
+    Megabytes,
 }
 
-fn get_ffmpeg_path() -> Result<PathBuf, String> {
-    if let Ok(path) = which("ffmpeg") {
-        return Ok(path);
-    }
-    let local_path = if cfg!(windows) {
-        PathBuf::from("./ffmpeg-bin/ffmpeg.exe")
-    } else {
-        PathBuf::from("./ffmpeg-bin/ffmpeg")
-    };
-    if local_path.exists() {
-        return Ok(local_path);
-    }
-    println!("ffmpeg not found, downloading static binary...");
-    std::fs::create_dir_all("./ffmpeg-bin").map_err(|e| e.to_string())?;
-
-    #[cfg(target_os = "macos")]
-    let (url, bin_name) = (
-        "https://evermeet.cx/ffmpeg/ffmpeg-6.1.1.zip",
-        "ffmpeg"
-    );
-    #[cfg(target_os = "linux")]
-    let (url, bin_name) = (
-        "https://johnvansickle.com/ffmpeg/releases/ffmpeg-release-amd64-static.tar.xz",
-        "ffmpeg"
-    );
-    #[cfg(target_os = "windows")]
-    let (url, bin_name) = (
-        "https://www.gyan.dev/ffmpeg/builds/ffmpeg-release-essentials.zip",
-        "ffmpeg.exe"
-    );
-
-    let archive_path = "./ffmpeg-bin/ffmpeg_download";
-    let mut resp = reqwest::blocking::get(url).map_err(|e| e.to_string())?;
-    let mut out = std::fs::File::create(archive_path).map_err(|e| e.to_string())?;
-    std::io::copy(&mut resp, &mut out).map_err(|e| e.to_string())?;
-
-    #[cfg(target_os = "macos")]
-    {
-        let file = std::fs::File::open(archive_path).map_err(|e| e.to_string())?;
-        let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i).unwrap();
-            let outpath = file.sanitized_name();
-            if outpath.file_name().map(|n| n == bin_name).unwrap_or(false) {
-                let mut out_bin = std::fs::File::create("./ffmpeg-bin/ffmpeg").map_err(|e| e.to_string())?;
-                std::io::copy(&mut file, &mut out_bin).map_err(|e| e.to_string())?;
-                use std::os::unix::fs::PermissionsExt;
-                std::fs::set_permissions("./ffmpeg-bin/ffmpeg", std::fs::Permissions::from_mode(0o755)).ok();
-                break;
-            }
+#[derive(Clone, Copy, PartialEq)]
+enum Tab {
+    Downloads,
+    History,
+}
+
+struct PartiGuiApp {
+    tab: Tab,
+    url_input: String,
+    section_start: String,
+    section_end: String,
+    precise_trim: bool,
+    /// Title and date to name the single download with, overriding what the video or
+    /// playlist says; empty keeps those.
+    name_title: String,
+    name_date: String,
+    /// `None` downloads the playlist's first variant.
+    quality: Option<Quality>,
+    split_kind: SplitKind,
+    split_value: String,
+    save_chat: bool,
+    chat_format: ChatFormat,
+    save_subtitles: bool,
+    subtitle_format: SubtitleFormat,
+    embed_subtitles: bool,
+    overwrite: Overwrite,
+    ignore_free_space: bool,
+    /// The single download's progress, also used for validation messages.
+    single: DownloadProgress,
+    single_events: Option<Receiver<DownloadEvent>>,
+    /// Text of the speed limit field; the parsed limit lives in the queue's downloader.
+    rate_limit_input: String,
+    queue: DownloadQueue,
+    /// Channel lookups running in the background, answered with the videos to queue.
+    expanded: (Sender<ExpandedInput>, Receiver<ExpandedInput>),
+    /// Network settings as edited; they reach the queue's client when applied.
+    network: HttpSettings,
+    network_status: String,
+    output_format: Arc<Mutex<String>>,
+    download_folder: Arc<Mutex<Option<String>>>,
+    is_downloading: bool,
+    single_control: DownloadControl,
+    /// URL and options of the single download, so an unfinished one can be handed to the
+    /// queue when the app closes.
+    single_job: Option<(String, DownloadOptions)>,
+    /// The single download while it is looked up and until it is confirmed.
+    preview: Option<PreviewCard>,
+    /// Whether links copied to the clipboard are offered for queueing.
+    watch_clipboard: bool,
+    /// Started the first time the clipboard is watched.
+    clipboard_watcher: Option<ClipboardWatcher>,
+    /// Links copied while watching, waiting to be queued or dismissed.
+    clipboard_offers: Vec<String>,
+    /// Search text and result filter of the History tab.
+    history_query: String,
+    history_filter: Option<HistoryResult>,
+    history_status: String,
+    log: LogBuffer,
+    /// Least severe level shown in the log panel.
+    log_level: Level,
+}
+
+impl Default for PartiGuiApp {
+    fn default() -> Self {
+        let mut queue = DownloadQueue::load();
+        let network = HttpSettings::load();
+        if let Err(e) = queue.downloader.set_http(network.clone()) {
+            warn!("Ignoring saved network settings: {e}");
         }
-    }
-    #[cfg(target_os = "windows")]
-    {
-        let file = std::fs::File::open(archive_path).map_err(|e| e.to_string())?;
-        let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i).unwrap();
-            let outpath = file.sanitized_name();
-            if outpath.file_name().map(|n| n == bin_name).unwrap_or(false) {
-                let mut out_bin = std::fs::File::create("./ffmpeg-bin/ffmpeg.exe").map_err(|e| e.to_string())?;
-                std::io::copy(&mut file, &mut out_bin).map_err(|e| e.to_string())?;
-                break;
-            }
+        Self {
+            tab: Tab::Downloads,
+            url_input: String::new(),
+            section_start: String::new(),
+            section_end: String::new(),
+            precise_trim: false,
+            name_title: String::new(),
+            name_date: String::new(),
+            quality: None,
+            split_kind: SplitKind::Off,
+            split_value: String::new(),
+            save_chat: false,
+            chat_format: ChatFormat::WebVtt,
+            save_subtitles: false,
+            subtitle_format: SubtitleFormat::WebVtt,
+            embed_subtitles: false,
+            overwrite: Overwrite::default(),
+            ignore_free_space: false,
+            single: DownloadProgress::default(),
+            single_events: None,
+            rate_limit_input: queue.downloader.limiter.limit().map(format_bytes).unwrap_or_default(),
+            queue,
+            expanded: std::sync::mpsc::channel(),
+            network,
+            network_status: String::new(),
+            output_format: Arc::new(Mutex::new("ts".to_string())),
+            download_folder: Arc::new(Mutex::new(None)),
+            is_downloading: false,
+            single_control: DownloadControl::default(),
+            single_job: None,
+            preview: None,
+            watch_clipboard: false,
+            clipboard_watcher: None,
+            clipboard_offers: Vec::new(),
+            history_query: String::new(),
+            history_filter: None,
+            history_status: String::new(),
+            log: LogBuffer::default(),
+            log_level: Level::Info,
         }
     }
-    #[cfg(target_os = "linux")]
-    {
-        let file = std::fs::File::open(archive_path).map_err(|e| e.to_string())?;
-        let decompressor = xz2::read::XzDecoder::new(file);
-        let mut archive = tar::Archive::new(decompressor);
-        for entry in archive.entries().map_err(|e| e.to_string())? {
-            let mut entry = entry.map_err(|e| e.to_string())?;
-            let path = entry.path().map_err(|e| e.to_string())?;
-            if path.file_name().map(|n| n == bin_name).unwrap_or(false) {
-                let mut out_bin = std::fs::File::create("./ffmpeg-bin/ffmpeg").map_err(|e| e.to_string())?;
-                std::io::copy(&mut entry, &mut out_bin).map_err(|e| e.to_string())?;
-                use std::os::unix::fs::PermissionsExt;
-                std::fs::set_permissions("./ffmpeg-bin/ffmpeg", std::fs::Permissions::from_mode(0o755)).ok();
-                break;
-            }
+}
+
+impl PartiGuiApp {
+    /// The clip range entered in the single-video box, `None` when both fields are empty.
+    fn section(&self) -> Result<Option<Section>, String> {
+        let (start, end) = (self.section_start.trim(), self.section_end.trim());
+        if start.is_empty() && end.is_empty() {
+            return Ok(None);
         }
+        let start_secs = if start.is_empty() {
+            0.0
+        } else {
+            parse_timestamp(start).ok_or_else(|| format!("Invalid start time '{start}'"))?
+        };
+        let end_secs = if end.is_empty() {
+            None
+        } else {
+            Some(parse_timestamp(end).ok_or_else(|| format!("Invalid end time '{end}'"))?)
+        };
+        section_from_bounds(start_secs, end_secs).map(Some)
     }
 
-    std::fs::remove_file(archive_path).ok();
-    if local_path.exists() {
-        Ok(local_path)
-    } else {
-        Err("Failed to download and unpack ffmpeg".to_string())
+    /// Options shared by single and batch downloads.
+    fn options(&self) -> Result<DownloadOptions, String> {
+        Ok(DownloadOptions {
+            output_format: self.output_format.lock().unwrap().clone(),
+            download_folder: self.download_folder.lock().unwrap().clone(),
+            quality: self.quality,
+            split: self.split()?,
+            chat: self.save_chat.then_some(self.chat_format),
+            subtitles: self.save_subtitles.then_some(self.subtitle_format),
+            embed_subtitles: self.save_subtitles && self.embed_subtitles,
+            overwrite: self.overwrite,
+            ignore_free_space: self.ignore_free_space,
+            ..Default::default()
+        })
     }
-}
-
-fn convert_with_ffmpeg(input: &str, output: &str, format: &str) -> Result<(), String> {
-    let ffmpeg_path = get_ffmpeg_path()?;
-    println!("[DEBUG] Using ffmpeg at: {:?}", ffmpeg_path);
-    let mut cmd = std::process::Command::new(ffmpeg_path.clone());
-    cmd.arg("-y").arg("-i").arg(input);
 
-    match format {
-        "mp3" => { cmd.args(&["-vn", "-acodec", "libmp3lame"]); }
-        "wav" => { cmd.args(&["-vn", "-acodec", "pcm_s16le"]); }
-        _ => {}
+    /// Options for the single-video box, which also carries the clip range and naming.
+    fn single_options(&self) -> Result<DownloadOptions, String> {
+        let date = Some(self.name_date.trim().to_string()).filter(|d| !d.is_empty());
+        if let Some(date) = &date {
+            chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| format!("Invalid date '{date}', expected YYYY-MM-DD"))?;
+        }
+        Ok(DownloadOptions {
+            section: self.section()?,
+            precise_trim: self.precise_trim,
+            title: Some(self.name_title.trim().to_string()).filter(|t| !t.is_empty()),
+            date,
+            ..self.options()?
+        })
     }
 
-    cmd.arg(output);
-
-    println!("[DEBUG] Running: {:?} {:?}", cmd.get_program(), cmd.get_args());
+    /// Looks the single download up on a background thread, for [`PartiGuiApp::preview_ui`]
+    /// to show before it starts.
+    fn start_preview(&mut self, ctx: &egui::Context, url: String, options: DownloadOptions) {
+        let downloader = self.queue.downloader.clone();
+        let (sender, lookup) = std::sync::mpsc::channel();
+        let (target, ctx) = (url.clone(), ctx.clone());
+        std::thread::spawn(move || {
+            sender.send(downloader.preview(&target).map_err(|e| e.to_string())).ok();
+            ctx.request_repaint();
+        });
+        self.single.status.clear();
+        let quality = options.quality;
+        self.preview = Some(PreviewCard { url, options, lookup: Some(lookup), preview: None, thumbnail: None, quality });
+    }
 
-    let output = cmd.output().map_err(|e| format!("Failed to run ffmpeg: {e}"))?;
-    if !output.status.success() {
-        println!("[ERROR] ffmpeg stderr: {}", String::from_utf8_lossy(&output.stderr));
-        return Err(format!(
-            "ffmpeg failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
+    fn start_single(&mut self, url: String, options: DownloadOptions) {
+        self.single = DownloadProgress::with_status("Starting download...");
+        self.is_downloading = true;
+        self.single_control = DownloadControl::default();
+        self.single_job = Some((url.clone(), options.clone()));
+        let (_, events) = self.queue.downloader.spawn(url, options, self.single_control.clone());
+        self.single_events = Some(events);
     }
-    Ok(())
-}
 
-fn download_video(
-    client: &Client,
-    url: &str,
-    status: &Arc<Mutex<String>>,
-    progress: &Arc<Mutex<f32>>,
-    output_format: &str,
-    abort_flag: &Arc<AtomicBool>,
-    download_folder: Option<&str>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    println!("[DEBUG] download_video called with url: {url}");
-    let re = Regex::new(r"/video/(\d+)")?;
-    let caps = re.captures(url).ok_or("Could not extract video ID from URL")?;
-    let video_id = &caps[1];
-
-    let api_url = format!(
-        "https://api-backend.parti.com/parti_v2/profile/get_livestream_channel_info/recent/{}",
-        video_id
-    );
-    println!("[DEBUG] Fetching API: {api_url}");
-    let json: Value = client.get(&api_url).send()?.json()?;
-    println!("[DEBUG] API JSON: {json:#}");
-
-    let recording_path = json.get("livestream_recording")
-        .or_else(|| json.get("playback_url"))
-        .or_else(|| json.get("recording_url"))
-        .and_then(|v| v.as_str());
-
-    let recording_path = match recording_path {
-        Some(path) => path,
-        None => {
-            *status.lock().unwrap() = "Could not find a video playlist field in API response.".to_string();
-            return Err("No playlist field in API response".into());
+    /// The looked-up video: what it is, how long, its qualities with the estimated size of
+    /// the chosen one, and buttons to download it or not.
+    fn preview_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(card) = &mut self.preview else { return };
+        if let Some(lookup) = &card.lookup {
+            match lookup.try_recv() {
+                Ok(Ok(preview)) => {
+                    card.thumbnail = preview.thumbnail.as_deref().and_then(|bytes| load_thumbnail(ui.ctx(), bytes));
+                    card.preview = Some(preview);
+                    card.lookup = None;
+                }
+                Ok(Err(e)) => {
+                    self.single.status = e;
+                    self.preview = None;
+                    return;
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Looking up video...");
+                    });
+                    return;
+                }
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    self.preview = None;
+                    return;
+                }
+            }
         }
-    };
-
-    let playback_url = if recording_path.starts_with("http") {
-        recording_path.to_string()
-    } else {
-        format!("https://watch.parti.com/{}", recording_path)
-    };
-
-    let title = json.get("event_title").and_then(|v| v.as_str()).unwrap_or("parti_video");
-    let timestamp = json.get("event_start_ts").and_then(|v| v.as_i64()).unwrap_or(0);
-    let date = if timestamp > 0 {
-        let dt = NaiveDateTime::from_timestamp(timestamp, 0);
-        Utc.from_utc_datetime(&dt).format("%Y-%m-%d").to_string()
-    } else {
-        "unknown_date".to_string()
-    };
-    let filename = format!("{}_{}.ts", sanitize_filename(title), date);
-    let filepath = if let Some(folder) = download_folder {
-        Path::new(folder).join(&filename)
-    } else {
-        PathBuf::from(&filename)
-    };
-
-    *status.lock().unwrap() = format!("Fetching playlist for '{}'", title);
-
-    println!("[DEBUG] Fetching master playlist: {playback_url}");
-    let playlist = client.get(&playback_url).send()?.text()?;
+        let Some(preview) = &card.preview else { return };
+        let section = card.options.section;
+        let output_format = card.options.output_format.clone();
+        let size_label = |quality: Option<Quality>| {
+            preview
+                .estimated_size(quality, &output_format, section.as_ref())
+                .map_or_else(|| "unknown".to_string(), |size| format!("about {}", format_bytes(size)))
+        };
+        let mut confirmed = None;
+        ui.group(|ui| {
+            ui.horizontal(|ui| {
+                if let Some(texture) = &card.thumbnail {
+                    ui.add(egui::Image::from_texture(egui::load::SizedTexture::from_handle(texture)).max_width(160.0));
+                }
+                ui.vertical(|ui| {
+                    let info = &preview.info;
+                    ui.strong(&info.title);
+                    if let Some(channel) = &info.channel {
+                        ui.label(format!("by {channel}"));
+                    }
+                    if info.date != "unknown_date" {
+                        ui.label(format!("Streamed on {}", info.date));
+                    }
+                    ui.label(format!("Duration: {}", format_timestamp(preview.duration)));
+                    let qualities = preview.qualities();
+                    if !qualities.is_empty() {
+                        let label = |q: Option<Quality>| q.map_or("Playlist default".to_string(), |q| q.to_string());
+                        let mut choices = vec![None];
+                        for height in qualities.iter().filter_map(|v| v.height) {
+                            if !choices.contains(&Some(Quality::Height(height))) {
+                                choices.push(Some(Quality::Height(height)));
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Quality:");
+                            egui::ComboBox::from_id_source("preview_quality")
+                                .selected_text(label(card.quality))
+                                .show_ui(ui, |ui| {
+                                    for q in choices {
+                                        let text = format!("{} ({})", label(q), size_label(q));
+                                        ui.selectable_value(&mut card.quality, q, text);
+                                    }
+                                });
+                        });
+                    }
+                    ui.label(format!("Estimated size: {}", size_label(card.quality)));
+                    let folder = card.options.download_folder.as_deref().unwrap_or(".");
+                    let estimate = preview.estimated_size(card.quality, &output_format, section.as_ref());
+                    if let (Some(size), Some(free)) = (estimate, free_space(std::path::Path::new(folder))) {
+                        if size > free {
+                            ui.colored_label(
+                                egui::Color32::LIGHT_RED,
+                                format!("Only {} free in the download folder", format_bytes(free)),
+                            );
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(!self.is_downloading, egui::Button::new("Download")).clicked() {
+                            confirmed = Some(true);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            confirmed = Some(false);
+                        }
+                    });
+                });
+            });
+        });
+        match confirmed {
+            Some(true) => {
+                let card = self.preview.take().unwrap();
+                let options = DownloadOptions {
+                    quality: card.quality,
+                    resolved: card.preview.map(|p| p.info),
+                    ..card.options
+                };
+                self.start_single(card.url, options);
+            }
+            Some(false) => self.preview = None,
+            None => {}
+        }
+    }
 
-    let mut variant_urls = Vec::new();
-    for line in playlist.lines() {
-        let line = line.trim();
-        if line.ends_with("/playlist.m3u8") {
-            let full_url = if line.starts_with("http") {
-                line.to_string()
-            } else {
-                let base = Url::parse(&playback_url)?;
-                base.join(line)?.to_string()
-            };
-            variant_urls.push(full_url);
+    /// The History tab: past downloads, newest first, with search and what to do with them.
+    fn history_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.history_query).hint_text("Search title, URL, ID or file"));
+            let label = |r: Option<HistoryResult>| r.map_or("All".to_string(), |r| r.to_string());
+            egui::ComboBox::from_id_source("history_filter")
+                .selected_text(label(self.history_filter))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.history_filter, None, label(None));
+                    for r in [HistoryResult::Finished, HistoryResult::Failed, HistoryResult::Cancelled] {
+                        ui.selectable_value(&mut self.history_filter, Some(r), label(Some(r)));
+                    }
+                });
+        });
+        let matching = self.queue.history.search(&self.history_query, self.history_filter);
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!matching.is_empty(), egui::Button::new("Export CSV...")).clicked() {
+                let file = rfd::FileDialog::new().add_filter("CSV", &["csv"]).set_file_name("history.csv").save_file();
+                if let Some(path) = file {
+                    let entries = matching.iter().map(|&i| &self.queue.history.entries[i]);
+                    let exported = std::fs::File::create(&path).map_err(Into::into).and_then(|f| write_csv(entries, f));
+                    self.history_status = match exported {
+                        Ok(()) => format!("Exported {} downloads to {}", matching.len(), path.display()),
+                        Err(e) => format!("Exporting history: {e}"),
+                    };
+                }
+            }
+            if ui.add_enabled(!self.queue.history.entries.is_empty(), egui::Button::new("Clear history")).clicked() {
+                self.queue.history.clear();
+            }
+        });
+        if !self.history_status.is_empty() {
+            ui.label(&self.history_status);
+        }
+        if matching.is_empty() {
+            let empty = self.queue.history.entries.is_empty();
+            ui.label(if empty { "Nothing downloaded yet." } else { "No downloads match." });
+            return;
+        }
+        let (mut again, mut removed) = (None, None);
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for &index in &matching {
+                let entry = &self.queue.history.entries[index];
+                ui.group(|ui| {
+                    ui.strong(if entry.title.is_empty() { &entry.url } else { &entry.title }).on_hover_text(&entry.url);
+                    ui.label(format!(
+                        "{} · {} · {} · {}",
+                        entry.local_time(),
+                        entry.result,
+                        entry.format,
+                        format_bytes(entry.size)
+                    ));
+                    if let Some(error) = &entry.error {
+                        ui.colored_label(egui::Color32::LIGHT_RED, error);
+                    }
+                    for path in &entry.paths {
+                        ui.small(path.display().to_string());
+                    }
+                    ui.horizontal(|ui| {
+                        let file = entry.paths.first().filter(|p| p.exists());
+                        if ui.add_enabled(file.is_some(), egui::Button::new("Open file")).clicked() {
+                            if let Err(e) = open_path(file.unwrap()) {
+                                self.history_status = format!("Can't open the file: {e}");
+                            }
+                        }
+                        if ui.add_enabled(file.is_some(), egui::Button::new("Show in folder")).clicked() {
+                            if let Err(e) = reveal_path(file.unwrap()) {
+                                self.history_status = format!("Can't show the folder: {e}");
+                            }
+                        }
+                        if ui.button("Download again").clicked() {
+                            again = Some(index);
+                        }
+                        if ui.button("Remove").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                });
+            }
+        });
+        if let Some(index) = again {
+            let entry = self.queue.history.entries[index].clone();
+            self.history_status = format!("Queued {} again.", entry.url);
+            self.queue.add(entry.url, entry.options);
+        }
+        if let Some(index) = removed {
+            self.queue.history.remove(index);
         }
     }
 
-    if variant_urls.is_empty() {
-        variant_urls.push(playback_url.clone());
+    /// Queues a URL from the URL box or a batch file, or says why it is not one. Channels
+    /// are looked up on a background thread and their videos queued once the answer arrives.
+    fn queue_input(&mut self, input: &str, options: DownloadOptions) -> Result<(), String> {
+        match parse_input(input).map_err(|e| e.to_string())? {
+            Target::Channel(name) => {
+                let downloader = self.queue.downloader.clone();
+                let (sender, input) = (self.expanded.0.clone(), input.to_string());
+                std::thread::spawn(move || {
+                    let videos = downloader.expand(&input);
+                    sender.send(ExpandedInput { input, options, videos }).ok();
+                });
+                self.single.status = format!("Looking up the recordings of {name}...");
+            }
+            _ => {
+                self.queue.add(input.trim().to_string(), options);
+            }
+        }
+        Ok(())
     }
 
-    let variant_url = &variant_urls[0];
-    *status.lock().unwrap() = format!("Fetching segments for '{}'", title);
-    println!("[DEBUG] Fetching variant playlist: {variant_url}");
-    let resp = client.get(variant_url).send()?;
-    println!("[DEBUG] Variant playlist HTTP status: {}", resp.status());
-    let text = resp.text()?;
-    println!("[DEBUG] Variant playlist content (first 500 chars):\n{}", &text[..text.len().min(500)]);
-    if text.trim().is_empty() {
-        *status.lock().unwrap() = "Variant playlist is empty or not found.".to_string();
-        return Err("Variant playlist is empty".into());
+    /// Queues everything in a batch file, or nothing if any line of it is wrong.
+    fn add_batch_file(&mut self, path: &std::path::Path, defaults: &DownloadOptions) {
+        let check_url = |url: &str| parse_input(url).map(drop).map_err(|e| e.to_string());
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| {
+                parse_batch(&text, BatchFormat::from_path(path), defaults, &check_url).map_err(|errors| {
+                    let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                    format!("Nothing queued, {} problems to fix first:\n{}", errors.len(), lines.join("\n"))
+                })
+            });
+        match parsed {
+            Ok(entries) => {
+                self.single.status = format!("Queued {} entries from {}", entries.len(), path.display());
+                for entry in entries {
+                    // Already checked, so this can only start a channel lookup.
+                    self.queue_input(&entry.url, entry.options).ok();
+                }
+            }
+            Err(e) => self.single.status = e,
+        }
     }
-    let variant_playlist = text;
-
-    // Robust .ts segment extraction
-    let mut ts_urls = Vec::new();
-    let base = Url::parse(variant_url)?;
-    for line in variant_playlist.lines() {
-        let line = line.trim();
-        if line.ends_with(".ts") {
-            let seg_url = if line.starts_with("http") {
-                line.to_string()
-            } else {
-                base.join(line)?.to_string()
+
+    /// Queues what was dropped on the window: batch lists as with "Add List...", and the
+    /// video links found in any other file (such as a browser's link shortcut) or text.
+    fn add_dropped(&mut self, files: Vec<egui::DroppedFile>) {
+        let options = match self.options() {
+            Ok(options) => options,
+            Err(e) => {
+                self.single.status = e;
+                return;
+            }
+        };
+        for file in files {
+            let extension = file
+                .path
+                .as_ref()
+                .and_then(|p| p.extension())
+                .and_then(|e| e.to_str())
+                .map(str::to_ascii_lowercase);
+            if let (Some(path), Some("txt" | "csv" | "json")) = (&file.path, extension.as_deref()) {
+                self.add_batch_file(path, &options);
+                continue;
+            }
+            let text = match (&file.path, &file.bytes) {
+                (Some(path), _) => std::fs::read(path)
+                    .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+                    .unwrap_or_default(),
+                (None, Some(bytes)) => String::from_utf8_lossy(bytes).into_owned(),
+                (None, None) => String::new(),
             };
-            ts_urls.push(seg_url);
+            let name = file.path.as_ref().map_or(file.name.clone(), |p| p.display().to_string());
+            let links = find_video_urls(&text);
+            if links.is_empty() {
+                self.single.status = format!("No video links in {name}");
+                continue;
+            }
+            self.single.status = format!("Queued {} links from {name}", links.len());
+            for link in links {
+                self.queue_input(&link, options.clone()).ok();
+            }
         }
     }
 
-    *status.lock().unwrap() = format!("Downloading {} segments...", ts_urls.len());
-    *progress.lock().unwrap() = 0.0;
-
-    let mut out = BufWriter::new(File::create(&filepath)?);
-
-    for (i, seg_url) in ts_urls.iter().enumerate() {
-        if abort_flag.load(Ordering::Relaxed) {
-            *status.lock().unwrap() = "Aborted by user.".to_string();
-            *progress.lock().unwrap() = 1.0;
-            return Ok(());
+    fn rate_limit(&self) -> Result<Option<u64>, String> {
+        let input = self.rate_limit_input.trim();
+        if input.is_empty() {
+            return Ok(None);
         }
-        {
-            let mut progress_guard = progress.lock().unwrap();
-            *progress_guard = (i + 1) as f32 / ts_urls.len() as f32;
+        match parse_rate_limit(input) {
+            Some(limit) => Ok(Some(limit)),
+            None => Err(format!(
+                "Invalid speed limit '{input}', e.g. 500K or 5M (at least {}K)",
+                MIN_RATE_LIMIT / 1024
+            )),
         }
-        {
-            let mut status_guard = status.lock().unwrap();
-            *status_guard = format!(
-                "Downloading segment {}/{}...",
-                i + 1,
-                ts_urls.len()
-            );
+    }
+
+    fn split(&self) -> Result<Option<SplitBy>, String> {
+        let value = self.split_value.trim();
+        let invalid = || format!("Invalid part length '{value}'");
+        match self.split_kind {
+            SplitKind::Off => Ok(None),
+            SplitKind::Minutes => match value.parse::<f64>() {
+                Ok(minutes) if minutes > 0.0 => Ok(Some(SplitBy::Duration(minutes * 60.0))),
+                _ => Err(invalid()),
+            },
+            SplitKind::Megabytes => match value.parse::<f64>() {
+                Ok(mb) if mb > 0.0 => Ok(Some(SplitBy::Size((mb * 1024.0 * 1024.0) as u64))),
+                _ => Err(invalid()),
+            },
         }
-        let mut resp = client.get(seg_url).send()?;
-        std::io::copy(&mut resp, &mut out)?;
     }
-    *progress.lock().unwrap() = 1.0;
-    *status.lock().unwrap() = format!("Saved to {}", filepath.display());
-
-    // Convert if needed
-    if output_format != "ts" && !abort_flag.load(Ordering::Relaxed) {
-        let out_name = format!("{}_{}.{}", sanitize_filename(title), date, output_format);
-        let out_path = if let Some(folder) = download_folder {
-            Path::new(folder).join(&out_name)
-        } else {
-            PathBuf::from(&out_name)
-        };
-        *status.lock().unwrap() = format!("Converting to {}...", output_format);
-        match convert_with_ffmpeg(
-            &filepath.to_string_lossy(),
-            &out_path.to_string_lossy(),
-            output_format,
-        ) {
-            Ok(_) => {
-                *status.lock().unwrap() = format!("Saved to {}", out_path.display());
-            }
-            Err(e) => {
-                *status.lock().unwrap() = format!("Conversion failed: {}", e);
+}
+
+/// Reads the clipboard on its own thread (which can block on X11) and reports the video
+/// links in whatever is copied while it is enabled.
+struct ClipboardWatcher {
+    enabled: Arc<AtomicBool>,
+    links: Receiver<Result<Vec<String>, String>>,
+}
+
+impl ClipboardWatcher {
+    fn start(ctx: egui::Context) -> Self {
+        let enabled = Arc::new(AtomicBool::new(true));
+        let (sender, links) = std::sync::mpsc::channel();
+        let watching = enabled.clone();
+        std::thread::spawn(move || {
+            let mut clipboard = match arboard::Clipboard::new() {
+                Ok(clipboard) => clipboard,
+                Err(e) => {
+                    sender.send(Err(format!("Can't watch the clipboard: {e}"))).ok();
+                    ctx.request_repaint();
+                    return;
+                }
+            };
+            // Whatever was copied before watching started is not offered.
+            let mut last: Option<String> = None;
+            loop {
+                std::thread::sleep(std::time::Duration::from_millis(700));
+                if !watching.load(Ordering::Relaxed) {
+                    last = None;
+                    continue;
+                }
+                let text = clipboard.get_text().unwrap_or_default();
+                if last.replace(text.clone()).is_none_or(|last| last == text) {
+                    continue;
+                }
+                let found = find_video_urls(&text);
+                if !found.is_empty() {
+                    if sender.send(Ok(found)).is_err() {
+                        return;
+                    }
+                    ctx.request_repaint();
+                }
             }
-        }
+        });
+        Self { enabled, links }
     }
+}
 
-    Ok(())
+/// A video looked up from the single-video box, shown with a button to download it.
+struct PreviewCard {
+    url: String,
+    options: DownloadOptions,
+    /// Answers once the lookup started by [`PartiGuiApp::start_preview`] is done.
+    lookup: Option<Receiver<Result<Preview, String>>>,
+    preview: Option<Preview>,
+    thumbnail: Option<egui::TextureHandle>,
+    /// The quality picked on the card, starting from the one in the options.
+    quality: Option<Quality>,
 }
 
-struct PartiGuiApp {
-    url_input: String,
-    status: Arc<Mutex<String>>,
-    progress: Arc<Mutex<f32>>,
-    batch_video_status: Vec<Arc<Mutex<String>>>,
-    batch_video_progress: Vec<Arc<Mutex<f32>>>,
-    batch_video_urls: Vec<String>,
-    output_format: Arc<Mutex<String>>,
-    download_folder: Arc<Mutex<Option<String>>>,
-    is_downloading: bool,
-    is_batch_downloading: bool,
-    abort_single: Arc<AtomicBool>,
-    abort_batch: Arc<AtomicBool>,
+/// Decodes a downloaded thumbnail; images that do not decode are left out.
+fn load_thumbnail(ctx: &egui::Context, bytes: &[u8]) -> Option<egui::TextureHandle> {
+    let image = image::load_from_memory(bytes).ok()?.thumbnail(320, 180).to_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+    let image = egui::ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+    Some(ctx.load_texture("preview_thumbnail", image, Default::default()))
 }
 
-impl Default for PartiGuiApp {
-    fn default() -> Self {
-        Self {
-            url_input: String::new(),
-            status: Arc::new(Mutex::new(String::new())),
-            progress: Arc::new(Mutex::new(0.0)),
-            batch_video_status: Vec::new(),
-            batch_video_progress: Vec::new(),
-            batch_video_urls: Vec::new(),
-            output_format: Arc::new(Mutex::new("ts".to_string())),
-            download_folder: Arc::new(Mutex::new(None)),
-            is_downloading: false,
-            is_batch_downloading: false,
-            abort_single: Arc::new(AtomicBool::new(false)),
-            abort_batch: Arc::new(AtomicBool::new(false)),
-        }
+/// Opens `path` with the app the system uses for it.
+fn open_path(path: &std::path::Path) -> std::io::Result<()> {
+    let opener = if cfg!(windows) {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(opener).arg(path).spawn().map(drop)
+}
+
+/// Shows `path` in the file manager, selected where the system supports that.
+fn reveal_path(path: &std::path::Path) -> std::io::Result<()> {
+    let mut cmd;
+    if cfg!(windows) {
+        cmd = std::process::Command::new("explorer");
+        cmd.arg(format!("/select,{}", path.display()));
+    } else if cfg!(target_os = "macos") {
+        cmd = std::process::Command::new("open");
+        cmd.arg("-R").arg(path);
+    } else {
+        cmd = std::process::Command::new("xdg-open");
+        cmd.arg(path.parent().unwrap_or(path));
     }
+    cmd.spawn().map(drop)
+}
+
+/// The answer to a channel lookup started by [`PartiGuiApp::queue_input`].
+struct ExpandedInput {
+    input: String,
+    options: DownloadOptions,
+    videos: parti_vod_downloader::Result<Vec<String>>,
+}
+
+/// Queue row buttons, applied after the rows are drawn.
+enum QueueAction {
+    Move(usize, usize),
+    Hold(u64),
+    Pause(u64),
+    Resume(u64),
+    Cancel(u64),
+    Remove(u64),
 }
 
 impl App for PartiGuiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.vertical_centered(|ui| {
-                ui.heading("🎉 Parti Video Downloader");
-                ui.add_space(10.0);
-
-                // Download folder picker
-                ui.horizontal(|ui| {
-                    let folder = self.download_folder.lock().unwrap();
-                    let folder_display = folder.as_deref().unwrap_or("[Not set]");
-                    ui.label(format!("Download folder: {}", folder_display));
-                    drop(folder);
-                    if ui.button("Choose Folder...").clicked() {
-                        if let Some(folder) = rfd::FileDialog::new().pick_folder() {
-                            *self.download_folder.lock().unwrap() = Some(folder.display().to_string());
+        while let Ok(expanded) = self.expanded.1.try_recv() {
+            match expanded.videos {
+                Ok(videos) => {
+                    self.single.status = format!("Queued {} recordings of {}", videos.len(), expanded.input);
+                    for url in videos {
+                        self.queue.add(url, expanded.options.clone());
+                    }
+                }
+                Err(e) => self.single.status = e.to_string(),
+            }
+        }
+        if let Some(watcher) = &self.clipboard_watcher {
+            while let Ok(links) = watcher.links.try_recv() {
+                match links {
+                    Ok(links) => {
+                        for link in links {
+                            let known = self.clipboard_offers.contains(&link)
+                                || self.queue.items.iter().any(|item| item.url == link);
+                            if !known {
+                                self.clipboard_offers.push(link);
+                            }
                         }
                     }
-                });
-
-                // Output format dropdown
-                ui.horizontal(|ui| {
-                    ui.label("Output format:");
-                    let mut format = self.output_format.lock().unwrap();
-                    egui::ComboBox::from_id_source("format_combo")
-                        .selected_text(format.as_str())
-                        .show_ui(ui, |ui| {
-                            for f in ["ts", "mp4", "mp3", "wav", "wmv", "mov", "webm"] {
-                                ui.selectable_value(&mut *format, f.to_string(), f);
+                    Err(e) => {
+                        self.single.status = e;
+                        self.watch_clipboard = false;
+                    }
+                }
+            }
+        }
+        let (hovering, dropped) = ctx.input(|i| (!i.raw.hovered_files.is_empty(), i.raw.dropped_files.clone()));
+        if !dropped.is_empty() {
+            self.add_dropped(dropped);
+        }
+        if hovering {
+            let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop_overlay"));
+            let painter = ctx.layer_painter(layer);
+            let screen = ctx.screen_rect();
+            painter.rect_filled(screen, 0.0, egui::Color32::from_black_alpha(180));
+            painter.text(
+                screen.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop links or .txt/.csv/.json lists to queue them",
+                egui::FontId::proportional(20.0),
+                egui::Color32::WHITE,
+            );
+        }
+        self.queue.tick();
+        if let Some(events) = &self.single_events {
+            self.single.drain(events);
+        }
+        if self.is_downloading && self.single.is_finished() {
+            if let Some((url, options)) = self.single_job.take() {
+                if let Some(entry) = HistoryEntry::new(&url, &options, &self.single) {
+                    self.queue.history.record(entry);
+                }
+            }
+            self.is_downloading = false;
+            self.single_events = None;
+        }
+        if self.queue.is_active() || self.is_downloading {
+            ctx.request_repaint_after(std::time::Duration::from_millis(250));
+        }
+        egui::TopBottomPanel::bottom("log_panel").show(ctx, |ui| {
+            egui::CollapsingHeader::new(format!("Log ({} entries)", self.log.len()))
+                .id_source("log_panel_header")
+                .show(ui, |ui| {
+                    let entries: Vec<LogEntry> =
+                        self.log.entries().into_iter().filter(|e| e.level <= self.log_level).collect();
+                    ui.horizontal(|ui| {
+                        ui.label("Show:");
+                        egui::ComboBox::from_id_source("log_level")
+                            .selected_text(self.log_level.as_str())
+                            .show_ui(ui, |ui| {
+                                for level in [Level::Error, Level::Warn, Level::Info, Level::Debug] {
+                                    ui.selectable_value(&mut self.log_level, level, level.as_str());
+                                }
+                            });
+                        if ui.button("Copy").clicked() {
+                            let text = entries.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n");
+                            ui.output_mut(|o| o.copied_text = text);
+                        }
+                        if ui.button("Clear").clicked() {
+                            self.log.clear();
+                        }
+                    });
+                    egui::ScrollArea::vertical()
+                        .max_height(180.0)
+                        .stick_to_bottom(true)
+                        .auto_shrink([false, true])
+                        .show(ui, |ui| {
+                            for entry in &entries {
+                                let color = match entry.level {
+                                    Level::Error => egui::Color32::LIGHT_RED,
+                                    Level::Warn => egui::Color32::GOLD,
+                                    _ => ui.visuals().text_color(),
+                                };
+                                ui.label(
+                                    egui::RichText::new(format!("{} {}", entry.time.format("%H:%M:%S"), entry.message))
+                                        .monospace()
+                                        .color(color),
+                                );
                             }
                         });
                 });
-
-                ui.group(|ui| {
-                    ui.label("Download a single video:");
+        });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("🎉 Parti Video Downloader");
                     ui.horizontal(|ui| {
-                        ui.add(
-                            egui::TextEdit::singleline(&mut self.url_input)
-                                .hint_text("https://parti.com/video/..."),
-                        );
-                        if ui.button("Download Video").clicked() && !self.is_downloading {
-                            let url = self.url_input.trim().to_string();
-                            let format = self.output_format.lock().unwrap().clone();
-                            let download_folder = self.download_folder.lock().unwrap().clone();
-                            if url.is_empty() {
-                                *self.status.lock().unwrap() = "Please enter a video URL.".to_string();
-                            } else {
-                                *self.status.lock().unwrap() = "Starting download...".to_string();
-                                *self.progress.lock().unwrap() = 0.0;
-                                self.is_downloading = true;
-                                self.abort_single.store(false, Ordering::Relaxed);
-                                let status = self.status.clone();
-                                let progress = self.progress.clone();
-                                let abort_flag = self.abort_single.clone();
-                                let url_clone = url.clone();
-                                std::thread::spawn(move || {
-                                    let client = Client::builder()
-                                        .user_agent("Mozilla/5.0 (compatible; parti_video_dl/1.0)")
-                                        .build()
-                                        .unwrap();
-                                    let result = download_video(
-                                        &client,
-                                        &url_clone,
-                                        &status,
-                                        &progress,
-                                        &format,
-                                        &abort_flag,
-                                        download_folder.as_deref(),
-                                    );
-                                    if let Err(e) = result {
-                                        println!("[ERROR] Download thread: {e}");
-                                        *status.lock().unwrap() = format!("Error: {}", e);
-                                        *progress.lock().unwrap() = 1.0;
+                        ui.selectable_value(&mut self.tab, Tab::Downloads, "Downloads");
+                        ui.selectable_value(&mut self.tab, Tab::History, "History");
+                    });
+                    ui.add_space(10.0);
+                    if self.tab == Tab::History {
+                        self.history_ui(ui);
+                        return;
+                    }
+
+                    // Clipboard watcher and the links it found
+                    let watch = ui
+                        .checkbox(&mut self.watch_clipboard, "Offer to queue copied video links")
+                        .on_hover_text("Watch the clipboard while the app is open. Links can also be dropped onto the window.");
+                    if watch.changed() {
+                        match &self.clipboard_watcher {
+                            Some(watcher) => watcher.enabled.store(self.watch_clipboard, Ordering::Relaxed),
+                            None => self.clipboard_watcher = Some(ClipboardWatcher::start(ctx.clone())),
+                        }
+                    }
+                    if !self.clipboard_offers.is_empty() {
+                        let mut queued = Vec::new();
+                        let mut dismissed = Vec::new();
+                        ui.group(|ui| {
+                            for link in &self.clipboard_offers {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("Copied: {link}"));
+                                    if ui.small_button("Queue").clicked() {
+                                        queued.push(link.clone());
+                                    }
+                                    if ui.small_button("Dismiss").clicked() {
+                                        dismissed.push(link.clone());
                                     }
                                 });
                             }
+                            if self.clipboard_offers.len() > 1 {
+                                ui.horizontal(|ui| {
+                                    if ui.button("Queue all").clicked() {
+                                        queued = self.clipboard_offers.clone();
+                                    }
+                                    if ui.button("Dismiss all").clicked() {
+                                        dismissed = self.clipboard_offers.clone();
+                                    }
+                                });
+                            }
+                        });
+                        if !queued.is_empty() {
+                            match self.options() {
+                                Ok(options) => {
+                                    for link in &queued {
+                                        self.queue_input(link, options.clone()).ok();
+                                    }
+                                    dismissed.extend(queued);
+                                }
+                                Err(e) => self.single.status = e,
+                            }
                         }
-                    });
-                    if self.is_downloading {
-                        ui.add(egui::ProgressBar::new(*self.progress.lock().unwrap()).show_percentage());
-                        if ui.button("Abort").clicked() {
-                            self.abort_single.store(true, Ordering::Relaxed);
+                        self.clipboard_offers.retain(|link| !dismissed.contains(link));
+                    }
+
+                    // Download folder picker
+                    ui.horizontal(|ui| {
+                        let folder = self.download_folder.lock().unwrap();
+                        let folder_display = folder.as_deref().unwrap_or("[Not set]");
+                        ui.label(format!("Download folder: {}", folder_display));
+                        drop(folder);
+                        if ui.button("Choose Folder...").clicked() {
+                            if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                                *self.download_folder.lock().unwrap() = Some(folder.display().to_string());
+                            }
                         }
-                        if *self.progress.lock().unwrap() >= 1.0 {
-                            self.is_downloading = false;
+                    });
+
+                    // Output format dropdown
+                    ui.horizontal(|ui| {
+                        ui.label("Output format:");
+                        let mut format = self.output_format.lock().unwrap();
+                        egui::ComboBox::from_id_source("format_combo")
+                            .selected_text(format.as_str())
+                            .show_ui(ui, |ui| {
+                                for f in OUTPUT_FORMATS {
+                                    ui.selectable_value(&mut *format, f.to_string(), *f);
+                                }
+                            });
+                        drop(format);
+                        ui.label("Quality:");
+                        let label = |q: Option<Quality>| q.map_or("Playlist default".to_string(), |q| q.to_string());
+                        egui::ComboBox::from_id_source("quality_combo")
+                            .selected_text(label(self.quality))
+                            .show_ui(ui, |ui| {
+                                let choices = [None, Some(Quality::Best)]
+                                    .into_iter()
+                                    .chain([1080, 720, 480, 360].map(|h| Some(Quality::Height(h))))
+                                    .chain([Some(Quality::Worst)]);
+                                for q in choices {
+                                    ui.selectable_value(&mut self.quality, q, label(q));
+                                }
+                            });
+                    });
+
+                    // Splitting long VODs into parts
+                    ui.horizontal(|ui| {
+                        ui.label("Split into parts:");
+                        egui::ComboBox::from_id_source("split_combo")
+                            .selected_text(match self.split_kind {
+                                SplitKind::Off => "Don't split",
+                                SplitKind::Minutes => "Every N minutes",
+                                SplitKind::Megabytes => "Every N MB",
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.split_kind, SplitKind::Off, "Don't split");
+                                ui.selectable_value(&mut self.split_kind, SplitKind::Minutes, "Every N minutes");
+                                ui.selectable_value(&mut self.split_kind, SplitKind::Megabytes, "Every N MB");
+                            });
+                        if self.split_kind != SplitKind::Off {
+                            ui.add(egui::TextEdit::singleline(&mut self.split_value).desired_width(60.0));
                         }
-                    }
-                    let status = self.status.lock().unwrap();
-                    if !status.is_empty() {
-                        ui.label(&*status);
-                    }
-                });
+                    });
 
-                ui.add_space(20.0);
+                    // Chat replay export
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.save_chat, "Save chat replay as");
+                        ui.add_enabled_ui(self.save_chat, |ui| {
+                            egui::ComboBox::from_id_source("chat_combo")
+                                .selected_text(self.chat_format.extension())
+                                .show_ui(ui, |ui| {
+                                    for f in [ChatFormat::WebVtt, ChatFormat::Ass] {
+                                        ui.selectable_value(&mut self.chat_format, f, f.extension());
+                                    }
+                                });
+                        });
+                    });
 
-                ui.group(|ui| {
-                    ui.label("Download a list of videos:");
-                    if ui.button("Choose .txt File...").clicked() && !self.is_batch_downloading {
-                        if let Some(path) = rfd::FileDialog::new()
-                            .add_filter("Text", &["txt"])
-                            .pick_file()
-                        {
-                            let file = File::open(&path).unwrap();
-                            let reader = io::BufReader::new(file);
-                            let urls: Vec<String> = reader
-                                .lines()
-                                .filter_map(|l| l.ok())
-                                .filter(|l| !l.trim().is_empty())
-                                .collect();
-
-                            self.batch_video_status = urls.iter().map(|_| Arc::new(Mutex::new(String::new()))).collect();
-                            self.batch_video_progress = urls.iter().map(|_| Arc::new(Mutex::new(0.0))).collect();
-                            self.batch_video_urls = urls.clone();
-                            self.is_batch_downloading = true;
-                            self.abort_batch.store(false, Ordering::Relaxed);
-
-                            let status_vec = self.batch_video_status.clone();
-                            let progress_vec = self.batch_video_progress.clone();
-                            let format = self.output_format.lock().unwrap().clone();
-                            let abort_flag = self.abort_batch.clone();
-                            let download_folder = self.download_folder.lock().unwrap().clone();
-
-                            std::thread::spawn(move || {
-                                let client = Client::builder()
-                                    .user_agent("Mozilla/5.0 (compatible; parti_video_dl/1.0)")
-                                    .build()
-                                    .unwrap();
-                                for (i, url) in urls.iter().enumerate() {
-                                    if abort_flag.load(Ordering::Relaxed) {
-                                        *status_vec[i].lock().unwrap() = "Aborted by user.".to_string();
-                                        *progress_vec[i].lock().unwrap() = 1.0;
-                                        continue;
+                    // Subtitle tracks advertised by the playlist
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.save_subtitles, "Save subtitles as");
+                        ui.add_enabled_ui(self.save_subtitles, |ui| {
+                            egui::ComboBox::from_id_source("subtitle_combo")
+                                .selected_text(self.subtitle_format.extension())
+                                .show_ui(ui, |ui| {
+                                    for f in [SubtitleFormat::WebVtt, SubtitleFormat::Srt] {
+                                        ui.selectable_value(&mut self.subtitle_format, f, f.extension());
                                     }
-                                    let status = status_vec[i].clone();
-                                    let progress = progress_vec[i].clone();
-                                    *status.lock().unwrap() = "Starting...".to_string();
-                                    let result = download_video(
-                                        &client,
-                                        url,
-                                        &status,
-                                        &progress,
-                                        &format,
-                                        &abort_flag,
-                                        download_folder.as_deref(),
-                                    );
-                                    if let Err(e) = result {
-                                        *status.lock().unwrap() = format!("Error: {}", e);
-                                        *progress.lock().unwrap() = 1.0;
+                                });
+                            ui.checkbox(&mut self.embed_subtitles, "Embed into video")
+                                .on_hover_text("Mux the subtitles into mkv, mp4, mov and webm outputs");
+                        });
+                    });
+
+                    // Existing files and disk space
+                    ui.horizontal(|ui| {
+                        ui.label("If the file exists:");
+                        let label = |o: Overwrite| match o {
+                            Overwrite::Skip => "Skip",
+                            Overwrite::Overwrite => "Overwrite",
+                            Overwrite::Rename => "Rename (_2, _3, ...)",
+                        };
+                        egui::ComboBox::from_id_source("overwrite_combo")
+                            .selected_text(label(self.overwrite))
+                            .show_ui(ui, |ui| {
+                                for o in [Overwrite::Rename, Overwrite::Skip, Overwrite::Overwrite] {
+                                    ui.selectable_value(&mut self.overwrite, o, label(o));
+                                }
+                            });
+                        ui.checkbox(&mut self.ignore_free_space, "Ignore low disk space")
+                            .on_hover_text("Download even when the estimated size is more than the free space");
+                    });
+
+                    egui::CollapsingHeader::new("Network").show(ui, |ui| {
+                        egui::Grid::new("network_grid").num_columns(2).show(ui, |ui| {
+                            let proxy = &mut self.network.proxy;
+                            ui.label("Proxy:");
+                            ui.add(egui::TextEdit::singleline(&mut proxy.url).hint_text("socks5h://127.0.0.1:1080"))
+                                .on_hover_text("http://, https://, socks5:// or socks5h:// proxy. Leave empty to connect directly.");
+                            ui.end_row();
+                            ui.label("User:");
+                            ui.text_edit_singleline(&mut proxy.username);
+                            ui.end_row();
+                            ui.label("Password:");
+                            ui.add(egui::TextEdit::singleline(&mut proxy.password).password(true));
+                            ui.end_row();
+                            ui.label("Bypass for:");
+                            ui.add(egui::TextEdit::singleline(&mut proxy.no_proxy).hint_text("localhost, .example.com"))
+                                .on_hover_text("Comma-separated hosts, domains or IP ranges reached without the proxy");
+                            ui.end_row();
+                            let auth = &mut self.network.auth;
+                            ui.label("Cookies file:");
+                            ui.horizontal(|ui| {
+                                ui.add(egui::TextEdit::singleline(&mut auth.cookies_file).hint_text("cookies.txt"))
+                                    .on_hover_text("Netscape-format cookies exported from a browser logged in to Parti");
+                                if ui.button("Browse...").clicked() {
+                                    if let Some(path) = rfd::FileDialog::new().add_filter("Cookies", &["txt"]).pick_file() {
+                                        auth.cookies_file = path.display().to_string();
                                     }
                                 }
                             });
-                        }
-                    }
+                            ui.end_row();
+                            ui.label("Token:");
+                            ui.add(egui::TextEdit::singleline(&mut auth.bearer_token).password(true))
+                                .on_hover_text("Bearer token of a Parti account, for members-only videos");
+                            ui.end_row();
+                            let network = &mut self.network;
+                            ui.label("User agent:");
+                            ui.add(egui::TextEdit::singleline(&mut network.user_agent).hint_text(DEFAULT_USER_AGENT));
+                            ui.end_row();
+                            ui.label("Referer:");
+                            ui.text_edit_singleline(&mut network.referer);
+                            ui.end_row();
+                            ui.label("Origin:");
+                            ui.text_edit_singleline(&mut network.origin);
+                            ui.end_row();
+                            ui.label("Extra headers:");
+                            ui.add(
+                                egui::TextEdit::multiline(&mut network.headers)
+                                    .hint_text("Name: value")
+                                    .desired_rows(2),
+                            )
+                            .on_hover_text("One header per line");
+                            ui.end_row();
+                            ui.label("Timeouts:");
+                            ui.horizontal(|ui| {
+                                ui.label("connect");
+                                ui.add(egui::DragValue::new(&mut network.connect_timeout).suffix(" s"));
+                                ui.label("read");
+                                ui.add(egui::DragValue::new(&mut network.read_timeout).suffix(" s"))
+                                    .on_hover_text("A request that stalls this long fails and is retried. 0 waits forever.");
+                            });
+                            ui.end_row();
+                        });
+                        ui.horizontal(|ui| {
+                            if ui.button("Apply").clicked() {
+                                self.network_status = match self.queue.downloader.set_http(self.network.clone()) {
+                                    Ok(()) => {
+                                        match self.network.save() {
+                                            Ok(()) => "Applied to new downloads.".to_string(),
+                                            Err(e) => format!("Applied, but not saved: {e}"),
+                                        }
+                                    }
+                                    Err(e) => e.to_string(),
+                                };
+                            }
+                            ui.label(&self.network_status);
+                        });
+                    });
 
-                    if self.is_batch_downloading {
-                        if ui.button("Abort Batch").clicked() {
-                            self.abort_batch.store(true, Ordering::Relaxed);
+                    ui.group(|ui| {
+                        ui.label("Download a single video:");
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.url_input)
+                                    .hint_text("https://parti.com/video/..."),
+                            );
+                            if ui.button("Add to Queue").clicked() {
+                                let url = self.url_input.trim().to_string();
+                                match self.single_options() {
+                                    _ if url.is_empty() => {
+                                        self.single.status = "Please enter a video URL.".to_string();
+                                    }
+                                    Ok(options) => match self.queue_input(&url, options) {
+                                        Ok(()) => self.url_input.clear(),
+                                        Err(e) => self.single.status = e,
+                                    },
+                                    Err(e) => self.single.status = e,
+                                }
+                            }
+                            if ui.button("Download Video").clicked() && !self.is_downloading {
+                                let url = self.url_input.trim().to_string();
+                                let options = self.single_options();
+                                if url.is_empty() {
+                                    self.single.status = "Please enter a video URL.".to_string();
+                                } else if let Err(e) = &options {
+                                    self.single.status = e.clone();
+                                } else if let Err(e) = parse_input(&url) {
+                                    self.single.status = e.to_string();
+                                } else {
+                                    self.start_preview(ctx, url, options.unwrap());
+                                }
+                            }
+                        });
+                        self.preview_ui(ui);
+                        ui.horizontal(|ui| {
+                            ui.label("Name as");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.name_title)
+                                    .hint_text("video title")
+                                    .desired_width(160.0),
+                            );
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.name_date)
+                                    .hint_text("YYYY-MM-DD")
+                                    .desired_width(80.0),
+                            )
+                            .on_hover_text("Leave empty to use the title and date of the video or playlist");
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Clip from");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.section_start)
+                                    .hint_text("0:00:00")
+                                    .desired_width(70.0),
+                            );
+                            ui.label("to");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.section_end)
+                                    .hint_text("end")
+                                    .desired_width(70.0),
+                            );
+                            ui.add_enabled(
+                                self.split_kind == SplitKind::Off,
+                                egui::Checkbox::new(&mut self.precise_trim, "Trim precisely"),
+                            )
+                            .on_hover_text("Re-encode with ffmpeg so the clip starts and ends exactly at these times")
+                            .on_disabled_hover_text("Not available when splitting into parts");
+                        });
+                        if self.is_downloading {
+                            ui.add(egui::ProgressBar::new(self.single.fraction).show_percentage());
+                            if let Some(summary) = self.single.transfer_summary() {
+                                ui.label(summary);
+                            }
+                            ui.horizontal(|ui| {
+                                if self.single_control.is_paused() {
+                                    if ui.button("Resume").clicked() {
+                                        self.single_control.resume();
+                                    }
+                                } else if ui.button("Pause").clicked() {
+                                    self.single_control.pause();
+                                }
+                                if ui.button("Abort").clicked() {
+                                    self.single_control.cancel();
+                                }
+                            });
                         }
-                    }
+                        if !self.single.status.is_empty() {
+                            ui.label(&self.single.status);
+                        }
+                    });
 
-                    if !self.batch_video_urls.is_empty() {
-                        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
-                            for (i, url) in self.batch_video_urls.iter().enumerate() {
-                                let status = self.batch_video_status[i].lock().unwrap();
-                                let progress = *self.batch_video_progress[i].lock().unwrap();
-                                ui.group(|ui| {
-                                    ui.label(format!("Video {}: {}", i + 1, url));
-                                    ui.add(egui::ProgressBar::new(progress).show_percentage());
-                                    ui.label(&*status);
-                                });
+                    ui.add_space(20.0);
+
+                    ui.group(|ui| {
+                        ui.label("Download queue:");
+                        ui.horizontal(|ui| {
+                            if ui
+                                .button("Add List...")
+                                .on_hover_text("A .txt with one URL per line, or a .csv or .json list whose rows can set their own format, quality, filename, folder and section")
+                                .clicked()
+                            {
+                                match self.options() {
+                                    Err(e) => self.single.status = e,
+                                    Ok(options) => {
+                                        if let Some(path) = rfd::FileDialog::new()
+                                            .add_filter("Batch list", &["txt", "csv", "json"])
+                                            .pick_file()
+                                        {
+                                            self.add_batch_file(&path, &options);
+                                        }
+                                    }
+                                }
+                            }
+                            ui.label("Parallel downloads:");
+                            let mut concurrency = self.queue.concurrency;
+                            if ui.add(egui::DragValue::new(&mut concurrency).clamp_range(1..=8)).changed() {
+                                self.queue.set_concurrency(concurrency);
+                            }
+                            ui.label("Speed limit:");
+                            let limit_field = ui
+                                .add(
+                                    egui::TextEdit::singleline(&mut self.rate_limit_input)
+                                        .hint_text("none")
+                                        .desired_width(50.0),
+                                )
+                                .on_hover_text("Shared by all downloads, e.g. 5M for 5 MB/s. Leave empty for no limit.");
+                            // An invalid limit leaves the last valid one in place.
+                            match self.rate_limit() {
+                                Ok(limit) if limit_field.changed() => self.queue.set_rate_limit(limit),
+                                Ok(_) => {}
+                                Err(e) => {
+                                    ui.colored_label(egui::Color32::LIGHT_RED, e);
+                                }
                             }
                         });
-                        if self.is_batch_downloading
-                            && self.batch_video_progress.iter().all(|p| *p.lock().unwrap() >= 1.0)
-                        {
-                            self.is_batch_downloading = false;
+                        ui.horizontal(|ui| {
+                            let has_failed = self.queue.items.iter().any(|item| item.state == QueueState::Failed);
+                            if ui.add_enabled(has_failed, egui::Button::new("Retry failed")).clicked() {
+                                self.queue.retry_failed();
+                            }
+                            if ui.button("Clear finished").clicked() {
+                                self.queue.clear_finished();
+                            }
+                            if ui.add_enabled(self.queue.is_active(), egui::Button::new("Stop all")).clicked() {
+                                self.queue.stop_all();
+                            }
+                        });
+
+                        if !self.queue.items.is_empty() {
+                            let mut action = None;
+                            egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                                for (index, item) in self.queue.items.iter().enumerate() {
+                                    let status = item.progress.status.clone();
+                                    let progress = item.progress.fraction;
+                                    let row = ui.group(|ui| {
+                                        ui.horizontal(|ui| {
+                                            ui.dnd_drag_source(egui::Id::new(("queue_item", item.id)), index, |ui| {
+                                                ui.label("☰");
+                                            })
+                                            .response
+                                            .on_hover_text("Drag to reorder");
+                                            match &item.progress.info {
+                                                Some(info) => ui
+                                                    .label(format!("Video {}: {} ({})", index + 1, info.title, info.date))
+                                                    .on_hover_text(&item.url),
+                                                None => ui.label(format!("Video {}: {}", index + 1, item.url)),
+                                            };
+                                        });
+                                        ui.add(egui::ProgressBar::new(progress).show_percentage());
+                                        if let Some(summary) = item.progress.transfer_summary() {
+                                            ui.small(summary);
+                                        }
+                                        ui.horizontal(|ui| {
+                                            ui.label(&status);
+                                            match item.state {
+                                                QueueState::Queued if ui.small_button("Hold").clicked() => {
+                                                    action = Some(QueueAction::Hold(item.id));
+                                                }
+                                                QueueState::Held if ui.small_button("Resume").clicked() => {
+                                                    action = Some(QueueAction::Resume(item.id));
+                                                }
+                                                QueueState::Running
+                                                    if !item.control.is_cancelled()
+                                                        && ui.small_button("Pause").clicked() =>
+                                                {
+                                                    action = Some(QueueAction::Pause(item.id));
+                                                }
+                                                _ => {}
+                                            }
+                                            if item.state == QueueState::Running && item.control.is_paused() {
+                                                ui.label("Pausing...");
+                                            } else if item.state == QueueState::Running && item.control.is_cancelled() {
+                                                ui.label("Cancelling...");
+                                            } else if matches!(
+                                                item.state,
+                                                QueueState::Queued | QueueState::Held | QueueState::Running
+                                            ) && ui.small_button("Cancel").clicked()
+                                            {
+                                                action = Some(QueueAction::Cancel(item.id));
+                                            }
+                                            if item.state.is_finished() && ui.small_button("Remove").clicked() {
+                                                action = Some(QueueAction::Remove(item.id));
+                                            }
+                                        });
+                                    });
+                                    if let Some(from) = row.response.dnd_release_payload::<usize>() {
+                                        action = Some(QueueAction::Move(*from, index));
+                                    }
+                                }
+                            });
+                            match action {
+                                Some(QueueAction::Move(from, to)) => self.queue.move_item(from, to),
+                                Some(QueueAction::Hold(id)) => self.queue.hold(id),
+                                Some(QueueAction::Pause(id)) => self.queue.pause(id),
+                                Some(QueueAction::Resume(id)) => self.queue.resume(id),
+                                Some(QueueAction::Cancel(id)) => self.queue.cancel(id),
+                                Some(QueueAction::Remove(id)) => self.queue.remove(id),
+                                None => {}
+                            }
                         }
-                    }
-                });
+                    });
 
-                ui.add_space(20.0);
-                ui.label("Made with \u{2665} in Rust + egui");
+                    ui.add_space(20.0);
+                    ui.label("Made with \u{2665} in Rust + egui");
+                });
             });
         });
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // An unfinished single download continues from the queue on the next start.
+        if let Some((url, options)) = self.single_job.take().filter(|_| self.is_downloading) {
+            let paused = self.single_control.is_paused();
+            let id = self.queue.add(url, options);
+            if let Some(item) = self.queue.item_mut(id) {
+                item.control = DownloadControl::with_checkpoint(self.single_control.checkpoint());
+                if paused {
+                    item.state = QueueState::Held;
+                    item.progress.status = "Paused".to_string();
+                }
+            }
+        }
+        self.queue.save();
+    }
 }
 
 // --- main() with icon fix ---
 fn main() -> eframe::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(args));
+    }
+    let log = logging::init("gui", LevelFilter::Warn);
+
     // Load the icon image from assets
     let icon_bytes = include_bytes!("../assets/Icon.png");
     let image = image::load_from_memory(icon_bytes)
@@ -544,7 +1276,7 @@ fn main() -> eframe::Result<()> {
 
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([480.0, 480.0])
+            .with_inner_size([560.0, 720.0])
             .with_icon(icon_data),
         ..Default::default()
     };
@@ -553,6 +1285,6 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "Parti Video Downloader",
         native_options,
-        Box::new(|_cc| Box::new(PartiGuiApp::default())),
+        Box::new(|_cc| Box::new(PartiGuiApp { log, ..Default::default() })),
     )
 }
@@ -0,0 +1,288 @@
+//! HLS playlist parsing: variants and renditions of a master playlist, segments of a
+//! media playlist.
+
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::util::Section;
+
+fn resolve_uri(base: &Url, uri: &str) -> Result<String, url::ParseError> {
+    if uri.starts_with("http") {
+        Ok(uri.to_string())
+    } else {
+        Ok(base.join(uri)?.to_string())
+    }
+}
+
+/// Splits an attribute list such as `TYPE=AUDIO,GROUP-ID="aac",NAME="English"` into
+/// key/value pairs, keeping commas inside quoted values and stripping the quotes.
+fn parse_attributes(list: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = list.trim();
+    while !rest.is_empty() {
+        let Some((key, after)) = rest.split_once('=') else { break };
+        let (value, after) = if let Some(quoted) = after.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+        attributes.push((key.trim().to_string(), value.to_string()));
+        rest = after.trim_start_matches(',').trim_start();
+    }
+    attributes
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+/// One `#EXT-X-STREAM-INF` entry of a master playlist.
+#[derive(Clone, Debug)]
+pub struct Variant {
+    pub url: String,
+    pub bandwidth: Option<u64>,
+    /// Height in pixels, from `RESOLUTION=<width>x<height>`.
+    pub height: Option<u32>,
+    pub audio_group: Option<String>,
+}
+
+/// One `#EXT-X-MEDIA` entry of a master playlist (alternative audio, subtitles, ...).
+#[derive(Clone, Debug)]
+pub struct Rendition {
+    /// `AUDIO`, `VIDEO`, `SUBTITLES` or `CLOSED-CAPTIONS`.
+    pub kind: String,
+    pub group_id: String,
+    pub name: String,
+    pub language: Option<String>,
+    pub is_default: bool,
+    /// Renditions without a URI are muxed into the variant streams.
+    pub url: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MasterPlaylist {
+    pub variants: Vec<Variant>,
+    pub renditions: Vec<Rendition>,
+}
+
+/// Parses a master playlist. A media playlist yields no variants.
+pub fn parse_master_playlist(text: &str, base: &Url) -> Result<MasterPlaylist, url::ParseError> {
+    let mut master = MasterPlaylist::default();
+    let mut pending: Option<Vec<(String, String)>> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(list) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            pending = Some(parse_attributes(list));
+        } else if let Some(list) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let attrs = parse_attributes(list);
+            master.renditions.push(Rendition {
+                kind: attribute(&attrs, "TYPE").unwrap_or_default().to_string(),
+                group_id: attribute(&attrs, "GROUP-ID").unwrap_or_default().to_string(),
+                name: attribute(&attrs, "NAME").unwrap_or_default().to_string(),
+                language: attribute(&attrs, "LANGUAGE").map(str::to_string),
+                is_default: attribute(&attrs, "DEFAULT") == Some("YES"),
+                url: attribute(&attrs, "URI").map(|uri| resolve_uri(base, uri)).transpose()?,
+            });
+        } else if !line.is_empty() && !line.starts_with('#') {
+            if let Some(attrs) = pending.take() {
+                master.variants.push(Variant {
+                    url: resolve_uri(base, line)?,
+                    bandwidth: attribute(&attrs, "BANDWIDTH").and_then(|b| b.parse().ok()),
+                    height: attribute(&attrs, "RESOLUTION")
+                        .and_then(|r| r.split_once('x'))
+                        .and_then(|(_, h)| h.parse().ok()),
+                    audio_group: attribute(&attrs, "AUDIO").map(str::to_string),
+                });
+            } else if line.ends_with("/playlist.m3u8") {
+                // Older Parti masters list variant playlists without a STREAM-INF tag.
+                master.variants.push(Variant {
+                    url: resolve_uri(base, line)?,
+                    bandwidth: None,
+                    height: None,
+                    audio_group: None,
+                });
+            }
+        }
+    }
+    Ok(master)
+}
+
+/// What a playlist says about the recording, for naming downloads that skip the API.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaylistMetadata {
+    /// From a `#EXT-X-SESSION-DATA` entry whose `DATA-ID` ends in `title`.
+    pub title: Option<String>,
+    /// The first `#EXT-X-PROGRAM-DATE-TIME`, which only media playlists carry.
+    pub start: Option<DateTime<FixedOffset>>,
+}
+
+/// Reads the [`PlaylistMetadata`] of a master or media playlist.
+pub fn parse_playlist_metadata(text: &str) -> PlaylistMetadata {
+    let mut metadata = PlaylistMetadata::default();
+    for line in text.lines().map(str::trim) {
+        if let Some(list) = line.strip_prefix("#EXT-X-SESSION-DATA:") {
+            let attrs = parse_attributes(list);
+            let is_title = attribute(&attrs, "DATA-ID").map(|id| id.to_ascii_lowercase().ends_with("title"));
+            if metadata.title.is_none() && is_title == Some(true) {
+                metadata.title = attribute(&attrs, "VALUE").filter(|v| !v.trim().is_empty()).map(str::to_string);
+            }
+        } else if let Some(time) = line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:") {
+            // Encoders often write the offset without a colon (`+0000`), which RFC 3339 forbids.
+            metadata.start = metadata.start.or_else(|| {
+                DateTime::parse_from_rfc3339(time)
+                    .or_else(|_| DateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.f%z"))
+                    .ok()
+            });
+        }
+    }
+    metadata
+}
+
+/// Which variant of a master playlist to download.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Quality {
+    Best,
+    Worst,
+    /// The tallest variant no taller than this many pixels, or the smallest if all are.
+    Height(u32),
+}
+
+impl Quality {
+    /// Parses `best`, `worst`, `720p` or `720`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim().to_ascii_lowercase();
+        match s.as_str() {
+            "best" => Ok(Quality::Best),
+            "worst" => Ok(Quality::Worst),
+            _ => s
+                .trim_end_matches('p')
+                .parse()
+                .ok()
+                .filter(|&h| h > 0)
+                .map(Quality::Height)
+                .ok_or_else(|| format!("Invalid quality '{s}', expected best, worst or a height such as 720p")),
+        }
+    }
+}
+
+impl std::fmt::Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Quality::Best => f.write_str("best"),
+            Quality::Worst => f.write_str("worst"),
+            Quality::Height(h) => write!(f, "{h}p"),
+        }
+    }
+}
+
+/// The variant to download for `quality`; `None` takes the first one listed, which is the
+/// playlist's preferred variant. Variants are ranked by height, then bandwidth.
+pub fn pick_variant(master: &MasterPlaylist, quality: Option<Quality>) -> Option<&Variant> {
+    let rank = |v: &&Variant| (v.height.unwrap_or(0), v.bandwidth.unwrap_or(0));
+    let variants = master.variants.iter();
+    match quality {
+        None => master.variants.first(),
+        Some(Quality::Best) => variants.max_by_key(rank),
+        Some(Quality::Worst) => variants.min_by_key(rank),
+        Some(Quality::Height(max)) => variants
+            .clone()
+            .filter(|v| v.height.is_some_and(|h| h <= max))
+            .max_by_key(rank)
+            .or_else(|| variants.min_by_key(rank)),
+    }
+}
+
+/// Output formats that only keep the audio track.
+pub fn is_audio_only(format: &str) -> bool {
+    matches!(format, "mp3" | "wav")
+}
+
+/// The playlist to fetch for audio-only output: the first default audio rendition in the first
+/// variant's group (or the group's first rendition), any audio rendition, or failing that the
+/// lowest-bandwidth variant.
+pub fn pick_audio_playlist(master: &MasterPlaylist) -> Option<&str> {
+    let with_uri = |r: &&Rendition| r.kind == "AUDIO" && r.url.is_some();
+    let preferred_group = master.variants.first().and_then(|v| v.audio_group.as_deref());
+    let in_group = || {
+        master
+            .renditions
+            .iter()
+            .filter(with_uri)
+            .filter(|r| preferred_group.map(|g| r.group_id == g).unwrap_or(true))
+    };
+    let audio = in_group()
+        .find(|r| r.is_default)
+        .or_else(|| in_group().next())
+        .or_else(|| master.renditions.iter().find(with_uri));
+    if let Some(rendition) = audio {
+        return rendition.url.as_deref();
+    }
+    master
+        .variants
+        .iter()
+        .min_by_key(|v| v.bandwidth.unwrap_or(u64::MAX))
+        .map(|v| v.url.as_str())
+}
+
+/// One media segment of a variant playlist, with its `#EXTINF` duration in seconds.
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub url: String,
+    pub duration: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MediaPlaylist {
+    /// `#EXT-X-MAP` initialization section (fMP4 renditions), written before the segments.
+    pub init_url: Option<String>,
+    pub segments: Vec<Segment>,
+}
+
+/// Parses a media playlist into its segments, with URIs resolved against `base`.
+pub fn parse_media_playlist(text: &str, base: &Url) -> Result<MediaPlaylist, url::ParseError> {
+    let mut playlist = MediaPlaylist::default();
+    let mut duration = 0.0;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(list) = line.strip_prefix("#EXT-X-MAP:") {
+            let attrs = parse_attributes(list);
+            playlist.init_url = attribute(&attrs, "URI").map(|uri| resolve_uri(base, uri)).transpose()?;
+        } else if let Some(info) = line.strip_prefix("#EXTINF:") {
+            duration = info
+                .split(',')
+                .next()
+                .and_then(|d| d.trim().parse().ok())
+                .unwrap_or(0.0);
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let url = resolve_uri(base, line)?;
+            playlist.segments.push(Segment { url, duration });
+            duration = 0.0;
+        }
+    }
+    Ok(playlist)
+}
+
+/// Keeps only the segments overlapping `section`. Also returns the VOD time at which the
+/// first kept segment starts, so the clip can be trimmed precisely afterwards.
+pub fn select_segments(segments: &[Segment], section: &Section) -> (Vec<Segment>, f64) {
+    let mut selected = Vec::new();
+    let mut first_start = None;
+    let mut t = 0.0;
+    for seg in segments {
+        let seg_start = t;
+        let seg_end = t + seg.duration;
+        t = seg_end;
+        if seg_end <= section.start {
+            continue;
+        }
+        if section.end.map(|end| seg_start >= end).unwrap_or(false) {
+            break;
+        }
+        first_start.get_or_insert(seg_start);
+        selected.push(seg.clone());
+    }
+    (selected, first_start.unwrap_or(0.0))
+}
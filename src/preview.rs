@@ -0,0 +1,102 @@
+//! Looking at a video before downloading it: what it is, how long, in which qualities
+//! and roughly how big.
+
+use log::{debug, warn};
+use url::Url;
+
+use crate::download::Downloader;
+use crate::error::{Error, Result};
+use crate::http::check_status;
+use crate::playlist::{
+    is_audio_only, parse_master_playlist, parse_media_playlist, pick_audio_playlist, pick_variant, MasterPlaylist,
+    Quality, Variant,
+};
+use crate::resolver::VideoInfo;
+use crate::util::Section;
+
+/// What [`Downloader::preview`] found out about a video.
+#[derive(Clone, Debug)]
+pub struct Preview {
+    /// The resolved video; pass it on as [`crate::DownloadOptions::resolved`] to download
+    /// it without asking the API again.
+    pub info: VideoInfo,
+    /// Length in seconds, from the segments of the variant playlist.
+    pub duration: f64,
+    /// The playlist's variants, empty when the recording is a single media playlist.
+    pub master: MasterPlaylist,
+    /// The thumbnail image as downloaded, when there is one.
+    pub thumbnail: Option<Vec<u8>>,
+}
+
+impl Preview {
+    /// The variants as they can be chosen, tallest first.
+    pub fn qualities(&self) -> Vec<&Variant> {
+        let mut variants: Vec<&Variant> = self.master.variants.iter().collect();
+        variants.sort_by_key(|v| std::cmp::Reverse((v.height.unwrap_or(0), v.bandwidth.unwrap_or(0))));
+        variants
+    }
+
+    /// Roughly how many bytes downloading at `quality` as `output_format` (and only `section`)
+    /// will take, from the advertised bandwidth of the playlist that gets fetched. `None` when
+    /// the playlist gives none, as for an audio rendition picked for audio-only output.
+    pub fn estimated_size(
+        &self,
+        quality: Option<Quality>,
+        output_format: &str,
+        section: Option<&Section>,
+    ) -> Option<u64> {
+        let bandwidth = if is_audio_only(output_format) {
+            let url = pick_audio_playlist(&self.master)?;
+            self.master.variants.iter().find(|v| v.url == url)?.bandwidth?
+        } else {
+            pick_variant(&self.master, quality)?.bandwidth?
+        };
+        let seconds = match section {
+            Some(section) => section.end.unwrap_or(self.duration).min(self.duration) - section.start,
+            None => self.duration,
+        };
+        Some(estimate_size(bandwidth, seconds))
+    }
+}
+
+/// Bytes for `seconds` of a stream advertised at `bandwidth` bits per second.
+pub fn estimate_size(bandwidth: u64, seconds: f64) -> u64 {
+    (bandwidth as f64 / 8.0 * seconds.max(0.0)) as u64
+}
+
+impl Downloader {
+    /// Resolves `url` the same way [`Downloader::download`] does and reads its playlists,
+    /// without downloading any segments. A thumbnail that cannot be fetched is left out
+    /// rather than failing the preview.
+    pub fn preview(&self, url: &str) -> Result<Preview> {
+        let client = &self.client;
+        let info = self.resolver.resolve(client, url)?;
+        debug!("Previewing {url}: {}", info.playlist_url);
+        let text = check_status(client.get(&info.playlist_url).send()?)?.text()?;
+        let master = parse_master_playlist(&text, &Url::parse(&info.playlist_url)?)?;
+        let (media_url, media_text) = match master.variants.first() {
+            Some(variant) => (variant.url.as_str(), check_status(client.get(&variant.url).send()?)?.text()?),
+            None => (info.playlist_url.as_str(), text),
+        };
+        let media = parse_media_playlist(&media_text, &Url::parse(media_url)?)?;
+        if media.segments.is_empty() {
+            return Err(Error::EmptyVariant);
+        }
+        let duration = media.segments.iter().map(|s| s.duration).sum();
+        let thumbnail = info.thumbnail_url.as_deref().and_then(|thumbnail_url| {
+            let fetched = client
+                .get(thumbnail_url)
+                .send()
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.bytes());
+            match fetched {
+                Ok(bytes) => Some(bytes.to_vec()),
+                Err(e) => {
+                    warn!("Fetching thumbnail {thumbnail_url}: {e}");
+                    None
+                }
+            }
+        });
+        Ok(Preview { info, duration, master, thumbnail })
+    }
+}
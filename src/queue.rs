@@ -0,0 +1,353 @@
+//! A persistent download queue that runs a limited number of downloads at a time.
+
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+
+use crate::download::{
+    Checkpoint, DownloadControl, DownloadEvent, DownloadOptions, DownloadProgress, Downloader,
+};
+use crate::history::{History, HistoryEntry};
+use crate::util::data_dir;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum QueueState {
+    /// Waiting for a free download slot.
+    Queued,
+    /// Skipped by the scheduler until resumed.
+    Held,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl QueueState {
+    pub fn is_finished(self) -> bool {
+        matches!(self, QueueState::Done | QueueState::Failed | QueueState::Cancelled)
+    }
+}
+
+/// One download in a [`DownloadQueue`].
+pub struct QueueItem {
+    pub id: u64,
+    pub url: String,
+    pub options: DownloadOptions,
+    pub state: QueueState,
+    pub progress: DownloadProgress,
+    pub control: DownloadControl,
+    worker: Option<(std::thread::JoinHandle<()>, Receiver<DownloadEvent>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedQueueItem {
+    url: String,
+    options: DownloadOptions,
+    state: QueueState,
+    status: String,
+    #[serde(default)]
+    checkpoint: Option<Checkpoint>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedQueue {
+    concurrency: usize,
+    #[serde(default)]
+    rate_limit: Option<u64>,
+    items: Vec<SavedQueueItem>,
+}
+
+/// Downloads waiting to run, running and finished, in display order. Nothing runs on its
+/// own: [`DownloadQueue::tick`] reaps finished workers and starts queued items while fewer
+/// than `concurrency` are running, so the owner calls it regularly (every GUI frame).
+pub struct DownloadQueue {
+    pub items: Vec<QueueItem>,
+    next_id: u64,
+    pub concurrency: usize,
+    /// Shared by every download the queue starts, and by the GUI's single download.
+    pub downloader: Downloader,
+    /// Where every item that ends is recorded.
+    pub history: History,
+    /// Where the queue is saved after every change; `None` keeps it in memory only.
+    save_path: Option<PathBuf>,
+    dirty: bool,
+    last_save: std::time::Instant,
+}
+
+impl DownloadQueue {
+    pub fn new(concurrency: usize) -> Self {
+        Self::with_downloader(concurrency, Downloader::default())
+    }
+
+    pub fn with_downloader(concurrency: usize, downloader: Downloader) -> Self {
+        Self {
+            items: Vec::new(),
+            next_id: 1,
+            concurrency: concurrency.max(1),
+            downloader,
+            history: History::default(),
+            save_path: None,
+            dirty: false,
+            last_save: std::time::Instant::now(),
+        }
+    }
+
+    /// Restores the queue and the history saved in the data dir. Items that were running when the app
+    /// closed are queued again and continue from their checkpoint; paused ones stay held.
+    pub fn load() -> Self {
+        Self::open(data_dir().join("queue.json"))
+    }
+
+    /// Like [`DownloadQueue::load`], for the queue saved at `path` and the history next to it.
+    pub fn open(path: PathBuf) -> Self {
+        let saved: Option<SavedQueue> = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok());
+        let mut queue = Self::new(saved.as_ref().map(|s| s.concurrency).unwrap_or(2));
+        queue.downloader.limiter.set_limit(saved.as_ref().and_then(|s| s.rate_limit));
+        for item in saved.map(|s| s.items).unwrap_or_default() {
+            let id = queue.add(item.url, item.options);
+            let entry = queue.items.last_mut().unwrap();
+            entry.state = match item.state {
+                QueueState::Running => QueueState::Queued,
+                state => state,
+            };
+            if entry.state.is_finished() {
+                entry.progress.fraction = 1.0;
+            }
+            entry.progress.status = item.status;
+            entry.control = DownloadControl::with_checkpoint(item.checkpoint);
+            debug_assert_eq!(entry.id, id);
+        }
+        queue.history = History::open(path.with_file_name("history.json"));
+        queue.save_path = Some(path);
+        queue.dirty = false;
+        queue
+    }
+
+    pub fn save(&mut self) {
+        self.dirty = false;
+        let Some(path) = &self.save_path else { return };
+        let saved = SavedQueue {
+            concurrency: self.concurrency,
+            rate_limit: self.downloader.limiter.limit(),
+            items: self
+                .items
+                .iter()
+                .map(|item| SavedQueueItem {
+                    url: item.url.clone(),
+                    options: item.options.clone(),
+                    state: match item.state {
+                        QueueState::Running if item.control.is_paused() => QueueState::Held,
+                        state => state,
+                    },
+                    status: item.progress.status.clone(),
+                    checkpoint: item.control.checkpoint(),
+                })
+                .collect(),
+        };
+        self.last_save = std::time::Instant::now();
+        match serde_json::to_string_pretty(&saved) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(path, json) {
+                    error!("Saving queue: {e}");
+                }
+            }
+            Err(e) => error!("Saving queue: {e}"),
+        }
+    }
+
+    pub fn add(&mut self, url: String, options: DownloadOptions) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.items.push(QueueItem {
+            id,
+            url,
+            options,
+            state: QueueState::Queued,
+            progress: DownloadProgress::with_status("Queued"),
+            control: DownloadControl::default(),
+            worker: None,
+        });
+        self.dirty = true;
+        id
+    }
+
+    pub fn item_mut(&mut self, id: u64) -> Option<&mut QueueItem> {
+        self.items.iter_mut().find(|item| item.id == id)
+    }
+
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency = concurrency.max(1);
+        self.dirty = true;
+    }
+
+    /// Takes effect immediately, including for downloads already running.
+    pub fn set_rate_limit(&mut self, limit: Option<u64>) {
+        self.downloader.limiter.set_limit(limit);
+        self.dirty = true;
+    }
+
+    pub fn running(&self) -> usize {
+        self.items.iter().filter(|item| item.state == QueueState::Running).count()
+    }
+
+    /// Whether anything is running or still waiting for a slot.
+    pub fn is_active(&self) -> bool {
+        self.items
+            .iter()
+            .any(|item| matches!(item.state, QueueState::Running | QueueState::Queued))
+    }
+
+    pub fn tick(&mut self) {
+        for item in &mut self.items {
+            let Some((worker, events)) = &item.worker else { continue };
+            item.progress.drain(events);
+            if !worker.is_finished() {
+                continue;
+            }
+            let (worker, events) = item.worker.take().unwrap();
+            worker.join().ok();
+            item.progress.drain(&events);
+            if item.control.is_paused() && matches!(item.progress.outcome, Some(DownloadEvent::Aborted)) {
+                item.state = QueueState::Held;
+                item.progress.status = "Paused".to_string();
+                item.progress.fraction = item
+                    .control
+                    .checkpoint()
+                    .map_or(0.0, |c| c.next_segment as f32 / c.total_segments.max(1) as f32);
+                self.dirty = true;
+                continue;
+            }
+            if !item.progress.is_finished() {
+                let error = std::io::Error::other("Download thread panicked");
+                item.progress.apply(DownloadEvent::Failed { error: Arc::new(error.into()) });
+            }
+            item.state = match item.progress.outcome {
+                Some(DownloadEvent::Finished { .. }) => {
+                    *item.control.checkpoint.lock().unwrap() = None;
+                    QueueState::Done
+                }
+                Some(DownloadEvent::Aborted) => QueueState::Cancelled,
+                _ => QueueState::Failed,
+            };
+            if let Some(entry) = HistoryEntry::new(&item.url, &item.options, &item.progress) {
+                self.history.record(entry);
+            }
+            self.dirty = true;
+        }
+
+        let mut free = self.concurrency.saturating_sub(self.running());
+        for item in &mut self.items {
+            if free == 0 {
+                break;
+            }
+            if item.state != QueueState::Queued {
+                continue;
+            }
+            item.state = QueueState::Running;
+            item.progress = DownloadProgress::with_status("Starting...");
+            item.control.reset();
+            item.worker = Some(self.downloader.spawn(item.url.clone(), item.options.clone(), item.control.clone()));
+            free -= 1;
+            self.dirty = true;
+        }
+
+        // Running downloads move their checkpoints on without marking the queue dirty.
+        let checkpoints_stale = self.running() > 0 && self.last_save.elapsed() > std::time::Duration::from_secs(10);
+        if self.dirty || checkpoints_stale {
+            self.save();
+        }
+    }
+
+    /// Moves the item at index `from` so it ends up at index `to`.
+    pub fn move_item(&mut self, from: usize, to: usize) {
+        if from < self.items.len() && to < self.items.len() && from != to {
+            let item = self.items.remove(from);
+            self.items.insert(to, item);
+            self.dirty = true;
+        }
+    }
+
+    pub fn hold(&mut self, id: u64) {
+        if let Some(item) = self.item_mut(id).filter(|item| item.state == QueueState::Queued) {
+            item.state = QueueState::Held;
+            item.progress.status = "On hold".to_string();
+            self.dirty = true;
+        }
+    }
+
+    pub fn release(&mut self, id: u64) {
+        if let Some(item) = self.item_mut(id).filter(|item| item.state == QueueState::Held) {
+            item.state = QueueState::Queued;
+            item.progress.status = "Queued".to_string();
+            self.dirty = true;
+        }
+    }
+
+    /// Pauses a running download after its current segment. It stops at its checkpoint and is
+    /// put on hold, so its slot goes to the next queued item; resuming queues it again and it
+    /// continues from the checkpoint.
+    pub fn pause(&mut self, id: u64) {
+        if let Some(item) = self.item_mut(id).filter(|item| item.state == QueueState::Running) {
+            item.control.pause();
+            item.control.cancel();
+            self.dirty = true;
+        }
+    }
+
+    pub fn resume(&mut self, id: u64) {
+        self.release(id);
+    }
+
+    /// Cancels one item. A running download stops at its next segment and is marked
+    /// cancelled once its thread has finished; the rest of the queue carries on.
+    pub fn cancel(&mut self, id: u64) {
+        let Some(item) = self.item_mut(id) else { return };
+        match item.state {
+            QueueState::Queued | QueueState::Held => {
+                item.state = QueueState::Cancelled;
+                item.progress.status = "Cancelled".to_string();
+                self.dirty = true;
+            }
+            QueueState::Running => item.control.cancel(),
+            _ => {}
+        }
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        let before = self.items.len();
+        self.items.retain(|item| item.id != id || item.state == QueueState::Running);
+        self.dirty |= self.items.len() != before;
+    }
+
+    /// Queues failed items again. Cancelled ones stay cancelled.
+    pub fn retry_failed(&mut self) {
+        for item in &mut self.items {
+            if item.state == QueueState::Failed {
+                item.state = QueueState::Queued;
+                item.progress = DownloadProgress::with_status("Queued");
+                self.dirty = true;
+            }
+        }
+    }
+
+    pub fn clear_finished(&mut self) {
+        let before = self.items.len();
+        self.items.retain(|item| !item.state.is_finished());
+        self.dirty |= self.items.len() != before;
+    }
+
+    /// Aborts running downloads and puts everything still waiting on hold.
+    pub fn stop_all(&mut self) {
+        let ids: Vec<u64> = self.items.iter().map(|item| item.id).collect();
+        for id in ids {
+            self.hold(id);
+        }
+        for item in self.items.iter().filter(|item| item.state == QueueState::Running) {
+            item.control.cancel();
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! Looking up a video page to find its recording.
+
+use chrono::DateTime;
+use log::{debug, trace};
+use reqwest::blocking::Response;
+use serde_json::Value;
+use url::Url;
+
+use crate::error::{Error, Result};
+use crate::http::{check_status, HttpClient};
+use crate::playlist::{parse_master_playlist, parse_playlist_metadata};
+
+/// What the API tells us about a video: enough to name the output and find its playlist.
+#[derive(Clone, Debug)]
+pub struct VideoInfo {
+    pub video_id: String,
+    pub title: String,
+    /// `YYYY-MM-DD` of the stream start, or `unknown_date`.
+    pub date: String,
+    /// Unix time the stream started, 0 when unknown.
+    pub start_ts: i64,
+    /// The master (or media) playlist of the recording.
+    pub playlist_url: String,
+    /// The creator's name, when the API gives it.
+    pub channel: Option<String>,
+    /// A still image of the stream, when the API gives one.
+    pub thumbnail_url: Option<String>,
+}
+
+/// Turns the URL a user entered into a [`VideoInfo`]. [`PartiResolver`] asks the Parti
+/// backend; tests and other sites can plug in their own.
+pub trait Resolver: Send + Sync {
+    fn resolve(&self, client: &HttpClient, url: &str) -> Result<VideoInfo>;
+
+    /// The downloads an input stands for, checked before it is queued: the videos of a
+    /// channel, otherwise just the input itself.
+    fn expand(&self, _client: &HttpClient, input: &str) -> Result<Vec<String>> {
+        Ok(vec![input.trim().to_string()])
+    }
+
+    /// Where the chat replay of a resolved video can be fetched, if the site has one.
+    fn chat_url(&self, _info: &VideoInfo) -> Option<String> {
+        None
+    }
+
+    /// Base URLs of the site's hosts besides `parti.com` that are sent the bearer token,
+    /// see [`HttpClient::trust`].
+    fn token_hosts(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// What an input points at, see [`parse_input`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// A Parti recording, by video ID.
+    Video(String),
+    /// An HLS playlist URL, downloaded without asking the API.
+    Playlist(String),
+    /// A creator's channel page, by creator name.
+    Channel(String),
+}
+
+/// The first path segments of parti.com pages that are not channels.
+const NOT_CHANNELS: &[&str] = &["video", "videos", "explore", "search", "login", "signup", "settings", "live"];
+
+/// Whether `name` can be a creator name in a channel URL: letters, digits, `_`, `-` and
+/// `.`, and not a site page.
+fn is_creator_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        && !NOT_CHANNELS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+/// Like [`check_status`], but any other error status of the API is an [`Error::ApiHttp`].
+fn check_api_status(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() || matches!(status.as_u16(), 401 | 403) {
+        return check_status(response);
+    }
+    Err(Error::ApiHttp { status: status.as_u16() })
+}
+
+fn rejected(input: &str, reason: &str) -> Error {
+    Error::InvalidUrl(format!("'{input}': {reason}"))
+}
+
+/// Works out what a typed or pasted input refers to. Accepted are bare video IDs
+/// (`12345`), video URLs with or without a creator (`parti.com/<creator>/video/<id>`), a
+/// scheme or `www.`, and with any query string or fragment such as share links carry,
+/// `.m3u8` URLs on any host, and with the `channels` feature channel video lists
+/// (`parti.com/<creator>/videos`). Anything else is an [`Error::InvalidUrl`] saying what
+/// is wrong with it.
+pub fn parse_input(input: &str) -> Result<Target> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(rejected(input, "nothing entered"));
+    }
+    if input.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(Target::Video(input.to_string()));
+    }
+    let with_scheme = if input.contains("://") { input.to_string() } else { format!("https://{input}") };
+    let Ok(url) = Url::parse(&with_scheme) else {
+        return Err(rejected(input, "neither a URL nor a numeric video ID"));
+    };
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(rejected(input, "only http(s) URLs are supported"));
+    }
+    if url.path().to_ascii_lowercase().ends_with(".m3u8") {
+        return Ok(Target::Playlist(with_scheme));
+    }
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    if host != "parti.com" && !host.ends_with(".parti.com") {
+        return Err(rejected(input, "not a parti.com address or an .m3u8 playlist"));
+    }
+    let segments: Vec<&str> = url.path_segments().into_iter().flatten().filter(|s| !s.is_empty()).collect();
+    if let Some(i) = segments.iter().position(|s| *s == "video") {
+        return match segments.get(i + 1) {
+            Some(id) if id.chars().all(|c| c.is_ascii_digit()) => Ok(Target::Video(id.to_string())),
+            Some(id) => Err(rejected(input, &format!("the video ID '{id}' is not a number"))),
+            None => Err(rejected(input, "the video ID after /video/ is missing")),
+        };
+    }
+    match segments.as_slice() {
+        [] => Err(rejected(input, "this is the home page, not a video")),
+        // Only the video list, so that other one-segment pages (`/about`, `/terms`, ...)
+        // are not taken for channels.
+        [name, "videos"] if is_creator_name(name) => {
+            if cfg!(feature = "channels") {
+                Ok(Target::Channel(name.to_string()))
+            } else {
+                Err(rejected(input, "channel pages need a build with the `channels` feature"))
+            }
+        }
+        _ => Err(rejected(input, "no /video/<id> in the path")),
+    }
+}
+
+/// The Parti video and `.m3u8` playlist URLs in a piece of text, such as a chat message
+/// or web page copied to the clipboard, in order and without repeats. Unlike
+/// [`parse_input`], bare numbers and channel pages are not picked up.
+pub fn find_video_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let separators = |c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | '(' | ')' | '[' | ']');
+    for word in text.split(separators) {
+        // Internet shortcut files say `URL=https://...`.
+        let word = word.find("http").map_or(word, |start| &word[start..]);
+        let word = word.trim_end_matches(['.', ',', ';', '!', '?']);
+        if !word.contains("parti.com/") && !word.contains(".m3u8") {
+            continue;
+        }
+        if matches!(parse_input(word), Ok(Target::Video(_) | Target::Playlist(_))) && !urls.iter().any(|u| u == word) {
+            urls.push(word.to_string());
+        }
+    }
+    urls
+}
+
+/// Metadata for a playlist URL given directly. The playlist's own title and start time
+/// are used where it has them (see [`crate::playlist::PlaylistMetadata`]); otherwise the
+/// title and ID come from the path (`.../vod/42/master.m3u8` becomes video 42) and the
+/// date is unknown.
+fn playlist_info(client: &HttpClient, playlist_url: &str) -> Result<VideoInfo> {
+    let text = check_status(client.get(playlist_url).send()?)?.text()?;
+    let mut metadata = parse_playlist_metadata(&text);
+    if metadata.start.is_none() {
+        // Masters carry no timestamps, but their variants may.
+        let master = parse_master_playlist(&text, &Url::parse(playlist_url)?)?;
+        if let Some(variant) = master.variants.first() {
+            let variant_text = check_status(client.get(&variant.url).send()?)?.text()?;
+            metadata.start = parse_playlist_metadata(&variant_text).start;
+        }
+    }
+    let mut info = info_from_path(playlist_url);
+    if let Some(title) = metadata.title {
+        info.title = title;
+    }
+    if let Some(start) = metadata.start {
+        info.start_ts = start.timestamp();
+        info.date = start.format("%Y-%m-%d").to_string();
+    }
+    Ok(info)
+}
+
+fn info_from_path(playlist_url: &str) -> VideoInfo {
+    let path = Url::parse(playlist_url).map(|u| u.path().to_string()).unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let video_id = segments
+        .iter()
+        .rev()
+        .find(|s| s.chars().all(|c| c.is_ascii_digit()))
+        .map(|s| s.to_string())
+        .unwrap_or_default();
+    let title = if video_id.is_empty() {
+        let stem = segments.last().and_then(|f| f.rsplit_once('.')).map(|(stem, _)| stem).unwrap_or("playlist");
+        format!("parti_video_{stem}")
+    } else {
+        format!("parti_video_{video_id}")
+    };
+    VideoInfo {
+        video_id,
+        title,
+        date: "unknown_date".to_string(),
+        start_ts: 0,
+        playlist_url: playlist_url.to_string(),
+        channel: None,
+        thumbnail_url: None,
+    }
+}
+
+/// Downloads any HLS master or media playlist URL as given, whether or not it ends in
+/// `.m3u8`, without asking an API.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HlsResolver;
+
+impl Resolver for HlsResolver {
+    fn resolve(&self, client: &HttpClient, url: &str) -> Result<VideoInfo> {
+        let url = url.trim();
+        match Url::parse(url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => playlist_info(client, url),
+            _ => Err(Error::InvalidPlaylistUrl(format!("'{url}': expected an http(s) URL"))),
+        }
+    }
+}
+
+/// The production API.
+pub const PARTI_API_BASE: &str = "https://api-backend.parti.com/parti_v2/";
+/// Prefix for recording paths the API returns without a host.
+pub const PARTI_WATCH_BASE: &str = "https://watch.parti.com/";
+
+/// Resolves Parti video URLs and IDs (see [`parse_input`]) through the Parti backend API.
+/// Playlist URLs skip the API.
+#[derive(Clone, Debug)]
+pub struct PartiResolver {
+    /// Base of the API endpoints, ending in `/`.
+    pub api_base: String,
+    /// Base that relative recording paths are joined to, ending in `/`.
+    pub watch_base: String,
+}
+
+impl Default for PartiResolver {
+    fn default() -> Self {
+        Self {
+            api_base: PARTI_API_BASE.to_string(),
+            watch_base: PARTI_WATCH_BASE.to_string(),
+        }
+    }
+}
+
+impl PartiResolver {
+    /// A resolver for an API mirror (or a mock server) at `api_base`. Relative recording
+    /// paths are still joined to the production watch host.
+    pub fn with_api_base(api_base: &str) -> Self {
+        Self {
+            api_base: with_trailing_slash(api_base),
+            ..Self::default()
+        }
+    }
+}
+
+fn with_trailing_slash(base: &str) -> String {
+    if base.ends_with('/') {
+        base.to_string()
+    } else {
+        format!("{base}/")
+    }
+}
+
+impl Resolver for PartiResolver {
+    fn resolve(&self, client: &HttpClient, url: &str) -> Result<VideoInfo> {
+        let video_id = match parse_input(url)? {
+            Target::Video(id) => id,
+            Target::Playlist(playlist_url) => return playlist_info(client, &playlist_url),
+            Target::Channel(_) => {
+                return Err(rejected(url, "a channel page, not a video; queue it to download its recordings"))
+            }
+        };
+        let video_id = video_id.as_str();
+
+        let api_url = format!("{}profile/get_livestream_channel_info/recent/{}", self.api_base, video_id);
+        debug!("Fetching API: {api_url}");
+        let json: Value = check_api_status(client.get(&api_url).send()?)?.json()?;
+        trace!("API JSON: {json:#}");
+
+        let recording_path = json.get("livestream_recording")
+            .or_else(|| json.get("playback_url"))
+            .or_else(|| json.get("recording_url"))
+            .and_then(|v| v.as_str())
+            .ok_or(Error::NoPlaylistField)?;
+
+        let playlist_url = if recording_path.starts_with("http") {
+            recording_path.to_string()
+        } else {
+            format!("{}{}", self.watch_base, recording_path.trim_start_matches('/'))
+        };
+
+        let title = json.get("event_title").and_then(|v| v.as_str()).unwrap_or("parti_video");
+        let channel = ["user_name", "username", "creator_name", "channel_name"]
+            .iter()
+            .find_map(|key| json.get(key).and_then(|v| v.as_str()))
+            .map(str::to_string);
+        let thumbnail_url = ["event_thumbnail", "thumbnail_url", "thumbnail"]
+            .iter()
+            .find_map(|key| json.get(key).and_then(|v| v.as_str()))
+            .filter(|path| !path.is_empty())
+            .map(|path| {
+                if path.starts_with("http") {
+                    path.to_string()
+                } else {
+                    format!("{}{}", self.watch_base, path.trim_start_matches('/'))
+                }
+            });
+        let start_ts = json.get("event_start_ts").and_then(|v| v.as_i64()).unwrap_or(0);
+        let date = if start_ts > 0 {
+            DateTime::from_timestamp(start_ts, 0)
+                .map(|dt| dt.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| "unknown_date".to_string())
+        } else {
+            "unknown_date".to_string()
+        };
+        Ok(VideoInfo {
+            video_id: video_id.to_string(),
+            title: title.to_string(),
+            date,
+            start_ts,
+            playlist_url,
+            channel,
+            thumbnail_url,
+        })
+    }
+
+    /// A channel's recordings, newest first as the API lists them.
+    fn expand(&self, client: &HttpClient, input: &str) -> Result<Vec<String>> {
+        let Target::Channel(name) = parse_input(input)? else {
+            return Ok(vec![input.trim().to_string()]);
+        };
+        // Like the chat history, this endpoint is not documented.
+        let api_url = format!("{}profile/get_livestream_recordings/{}", self.api_base, name);
+        debug!("Fetching channel: {api_url}");
+        let json: Value = check_api_status(client.get(&api_url).send()?)?.json()?;
+        trace!("Channel JSON: {json:#}");
+        let list = ["recordings", "videos", "data"]
+            .iter()
+            .find_map(|key| json.get(key))
+            .unwrap_or(&json)
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let ids: Vec<String> = list
+            .iter()
+            .filter_map(|entry| {
+                let id = ["id", "video_id", "livestream_id"].iter().find_map(|key| entry.get(key)).unwrap_or(entry);
+                id.as_u64().map(|id| id.to_string()).or_else(|| id.as_str().map(str::to_string))
+            })
+            .collect();
+        if ids.is_empty() {
+            return Err(rejected(input, "the channel has no recordings"));
+        }
+        Ok(ids.iter().map(|id| format!("https://parti.com/video/{id}")).collect())
+    }
+
+    /// The API and watch hosts, which matter when they are a mirror's.
+    fn token_hosts(&self) -> Vec<String> {
+        vec![self.api_base.clone(), self.watch_base.clone()]
+    }
+
+    /// The backend does not document this endpoint; see [`crate::chat::fetch_chat`].
+    fn chat_url(&self, info: &VideoInfo) -> Option<String> {
+        Some(format!("{}profile/get_livestream_chat_history/{}", self.api_base, info.video_id))
+    }
+}
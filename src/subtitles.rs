@@ -0,0 +1,156 @@
+//! Subtitle renditions: stitching WebVTT segments into one `.vtt` or `.srt` file.
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::chat::vtt_timestamp;
+use crate::error::Result;
+use crate::http::HttpClient;
+use crate::playlist::parse_media_playlist;
+use crate::util::parse_timestamp;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SubtitleFormat {
+    WebVtt,
+    Srt,
+}
+
+impl SubtitleFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            SubtitleFormat::WebVtt => "vtt",
+            SubtitleFormat::Srt => "srt",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// Parses a WebVTT (`00:01.000`, `00:00:01.000`) or SRT (`00:00:01,000`) cue timestamp.
+fn parse_cue_timestamp(s: &str) -> Option<f64> {
+    parse_timestamp(&s.trim().replace(',', "."))
+}
+
+/// Parses one WebVTT file (or HLS subtitle segment). Also returns the offset given by its
+/// `X-TIMESTAMP-MAP` header, i.e. `MPEGTS / 90000 - LOCAL` in seconds.
+pub fn parse_webvtt(text: &str) -> (Vec<Cue>, Option<f64>) {
+    let mut cues = Vec::new();
+    let mut timestamp_map = None;
+    let text = text.replace("\r\n", "\n");
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().skip_while(|l| l.trim().is_empty());
+        let mut timing = None;
+        for line in lines.by_ref() {
+            if let Some(map) = line.strip_prefix("X-TIMESTAMP-MAP=") {
+                let mut mpegts = None;
+                let mut local = None;
+                for field in map.split(',') {
+                    match field.split_once(':') {
+                        Some(("MPEGTS", v)) => mpegts = v.trim().parse::<f64>().ok(),
+                        Some(("LOCAL", v)) => local = parse_cue_timestamp(v),
+                        _ => {}
+                    }
+                }
+                if let (Some(mpegts), Some(local)) = (mpegts, local) {
+                    timestamp_map = Some(mpegts / 90000.0 - local);
+                }
+            } else if let Some((start, rest)) = line.split_once("-->") {
+                let end = rest.split_whitespace().next().unwrap_or("");
+                timing = parse_cue_timestamp(start).zip(parse_cue_timestamp(end));
+                break;
+            }
+        }
+        if let Some((start, end)) = timing {
+            let text = lines.collect::<Vec<_>>().join("\n");
+            if !text.trim().is_empty() {
+                cues.push(Cue { start, end, text });
+            }
+        }
+    }
+    (cues, timestamp_map)
+}
+
+/// Downloads every segment of a subtitle playlist overlapping `clip` and stitches them into
+/// one cue list, with times relative to `clip.0`. Segments are re-based on the
+/// `X-TIMESTAMP-MAP` of the playlist's first segment (fetched for its map alone when the
+/// clip starts later); segments without one whose cues restart at zero are shifted to
+/// their position in the playlist.
+pub fn download_subtitles(client: &HttpClient, playlist_url: &str, clip: (f64, Option<f64>)) -> Result<Vec<Cue>> {
+    let fetch = |url: &str| -> Result<String> { Ok(client.get(url).send()?.error_for_status()?.text()?) };
+    let media = parse_media_playlist(&fetch(playlist_url)?, &Url::parse(playlist_url)?)?;
+    let (clip_start, clip_end) = clip;
+    let mut cues: Vec<Cue> = Vec::new();
+    let mut base_map = None;
+    let mut seg_start = 0.0;
+    for (index, seg) in media.segments.iter().enumerate() {
+        let start = seg_start;
+        seg_start += seg.duration;
+        if seg_start <= clip_start || clip_end.map(|end| start >= end).unwrap_or(false) {
+            continue;
+        }
+        let (seg_cues, map) = parse_webvtt(&fetch(&seg.url)?);
+        let shift = match map {
+            Some(map) => {
+                let base = match base_map {
+                    Some(base) => base,
+                    None if index == 0 => *base_map.insert(map),
+                    // Without a map of its own, the first segment is taken to start where this one's
+                    // position in the playlist says.
+                    None => *base_map.insert(parse_webvtt(&fetch(&media.segments[0].url)?).1.unwrap_or(map - start)),
+                };
+                map - base
+            }
+            None if seg_cues.first().map(|c| c.start + 1.0 < start).unwrap_or(false) => start,
+            None => 0.0,
+        };
+        for mut cue in seg_cues {
+            cue.start += shift;
+            cue.end += shift;
+            // Cues spanning a segment boundary are repeated in both segments.
+            if !cues.contains(&cue) {
+                cues.push(cue);
+            }
+        }
+    }
+    cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(cues
+        .into_iter()
+        .filter(|c| c.end > clip_start && clip_end.map(|end| c.start < end).unwrap_or(true))
+        .map(|c| Cue {
+            start: (c.start - clip_start).max(0.0),
+            end: c.end - clip_start,
+            text: c.text,
+        })
+        .collect())
+}
+
+/// Writes cues as a WebVTT or SRT file.
+pub fn render_subtitles(cues: &[Cue], format: SubtitleFormat) -> String {
+    match format {
+        SubtitleFormat::WebVtt => {
+            let mut out = "WEBVTT\n\n".to_string();
+            for cue in cues {
+                out.push_str(&format!("{} --> {}\n{}\n\n", vtt_timestamp(cue.start), vtt_timestamp(cue.end), cue.text));
+            }
+            out
+        }
+        SubtitleFormat::Srt => {
+            let mut out = String::new();
+            for (i, cue) in cues.iter().enumerate() {
+                out.push_str(&format!(
+                    "{}\n{} --> {}\n{}\n\n",
+                    i + 1,
+                    vtt_timestamp(cue.start).replace('.', ","),
+                    vtt_timestamp(cue.end).replace('.', ","),
+                    cue.text
+                ));
+            }
+            out
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Small helpers shared by the other modules: file names, times and sizes.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::download::MIN_RATE_LIMIT;
+
+/// Reduces a title to letters, digits and single underscores, for use in file names.
+pub fn sanitize_filename(s: &str) -> String {
+    let re = Regex::new(r"[^\w\d]+").unwrap();
+    let s = re.replace_all(s, "_");
+    let s = s.trim_matches('_');
+    let s = Regex::new(r"_+").unwrap().replace_all(s, "_");
+    s.to_string()
+}
+
+/// A time range (in seconds) to clip out of a VOD. `end == None` means "until the end".
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+/// Parses `SS`, `MM:SS` or `HH:MM:SS` (each optionally with a fractional part) into seconds.
+pub fn parse_timestamp(s: &str) -> Option<f64> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+    let mut total = 0.0;
+    let parts: Vec<&str> = s.split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    for (i, part) in parts.iter().enumerate() {
+        let value: f64 = part.parse().ok()?;
+        // Only the first component may be unbounded (e.g. "90:00"), the rest must be < 60.
+        if value < 0.0 || (i > 0 && value >= 60.0) {
+            return None;
+        }
+        total = total * 60.0 + value;
+    }
+    Some(total)
+}
+
+/// Parses `START-END`, `START-` or `-END` (e.g. `1:02:00-1:12:30`) into a [`Section`].
+pub fn parse_section(s: &str) -> Result<Section, String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("Invalid section '{s}', expected START-END"))?;
+    let start = if start.trim().is_empty() {
+        0.0
+    } else {
+        parse_timestamp(start).ok_or_else(|| format!("Invalid start time '{start}'"))?
+    };
+    let end = if end.trim().is_empty() {
+        None
+    } else {
+        Some(parse_timestamp(end).ok_or_else(|| format!("Invalid end time '{end}'"))?)
+    };
+    section_from_bounds(start, end)
+}
+
+/// Builds a [`Section`], rejecting ranges that end before they start.
+pub fn section_from_bounds(start: f64, end: Option<f64>) -> Result<Section, String> {
+    if let Some(end) = end {
+        if end <= start {
+            return Err("Section end must be after its start".to_string());
+        }
+    }
+    Ok(Section { start, end })
+}
+
+/// Formats seconds as `H:MM:SS`.
+pub fn format_timestamp(secs: f64) -> String {
+    let secs = secs.max(0.0);
+    let whole = secs as u64;
+    format!("{}:{:02}:{:02}", whole / 3600, (whole / 60) % 60, whole % 60)
+}
+
+/// Parses a byte size such as `500M`, `1.5G`, `800k` or `1048576` (binary multiples).
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.trim().parse().ok()?;
+    let multiplier = match unit.trim().to_ascii_lowercase().trim_end_matches('b') {
+        "" => 1u64,
+        "k" | "ki" => 1 << 10,
+        "m" | "mi" => 1 << 20,
+        "g" | "gi" => 1 << 30,
+        _ => return None,
+    };
+    if number <= 0.0 {
+        return None;
+    }
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Parses a download speed limit in bytes per second like [`parse_size`], refusing limits
+/// below [`MIN_RATE_LIMIT`].
+pub fn parse_rate_limit(s: &str) -> Option<u64> {
+    parse_size(s).filter(|&limit| limit >= MIN_RATE_LIMIT)
+}
+
+/// `1536` -> `1.5 KB`, using binary multiples like [`parse_size`].
+pub fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = "KB";
+    for next in ["MB", "GB"] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{value:.1} {unit}")
+}
+
+/// Bytes this user can still write to the volume holding `path` (or its closest existing
+/// parent folder), `None` where that cannot be told.
+pub fn free_space(path: &Path) -> Option<u64> {
+    let path = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+    available_bytes(path.ancestors().find(|p| p.exists())?)
+}
+
+#[cfg(unix)]
+fn available_bytes(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs only writes into the zeroed struct it is given.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // The field types differ between platforms.
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn available_bytes(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    let mut available = 0u64;
+    // SAFETY: `wide` is NUL-terminated and the totals that are not wanted may be null.
+    let ok = unsafe {
+        windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    (ok != 0).then_some(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available_bytes(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Per-user folder for state that outlives the app (the download queue, ...).
+pub fn data_dir() -> PathBuf {
+    let dir = dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("parti_vod_downloader");
+    std::fs::create_dir_all(&dir).ok();
+    dir
+}
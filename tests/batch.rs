@@ -0,0 +1,106 @@
+use parti_vod_downloader::batch::{parse_batch, BatchEntry, BatchError, BatchFormat};
+use parti_vod_downloader::playlist::Quality;
+use parti_vod_downloader::resolver::parse_input;
+use parti_vod_downloader::{DownloadOptions, Section};
+
+fn parse(text: &str, format: BatchFormat) -> Result<Vec<BatchEntry>, Vec<BatchError>> {
+    let defaults = DownloadOptions { output_format: "mp4".to_string(), ..Default::default() };
+    parse_batch(text, format, &defaults, &|url| parse_input(url).map(drop).map_err(|e| e.to_string()))
+}
+
+fn errors(text: &str, format: BatchFormat) -> Vec<String> {
+    parse(text, format).unwrap_err().iter().map(|e| e.to_string()).collect()
+}
+
+#[test]
+fn text_skips_blank_lines_and_comments() {
+    let entries = parse(
+        "# weekend streams\n\nhttps://parti.com/video/1\n  42   # the good one\nhttps://parti.com/video/3#t=10\n",
+        BatchFormat::Text,
+    )
+    .unwrap();
+    let urls: Vec<&str> = entries.iter().map(|e| e.url.as_str()).collect();
+    assert_eq!(urls, ["https://parti.com/video/1", "42", "https://parti.com/video/3#t=10"]);
+    assert!(entries.iter().all(|e| e.options.output_format == "mp4"));
+}
+
+#[test]
+fn csv_rows_override_options() {
+    let entries = parse(
+        "URL,format,quality,filename,folder,start,end\n\
+         # comments are allowed here too\n\
+         https://parti.com/video/1,mkv,720p,Day One.mkv,archive,1:00,2:30\n\
+         42,,,,,,\n\
+         43,mp3,best,,,10:00,\n",
+        BatchFormat::Csv,
+    )
+    .unwrap();
+    assert_eq!(entries.len(), 3);
+    let first = &entries[0].options;
+    assert_eq!(first.output_format, "mkv");
+    assert_eq!(first.quality, Some(Quality::Height(720)));
+    assert_eq!(first.filename.as_deref(), Some("Day One"));
+    assert_eq!(first.download_folder.as_deref(), Some("archive"));
+    assert_eq!(first.section, Some(Section { start: 60.0, end: Some(150.0) }));
+    assert_eq!(entries[1].options.output_format, "mp4");
+    assert_eq!(entries[1].options.section, None);
+    assert_eq!(entries[2].options.section, Some(Section { start: 600.0, end: None }));
+}
+
+#[test]
+fn json_accepts_urls_and_objects() {
+    let entries = parse(
+        r#"["42", {"url": "https://parti.com/video/43", "section": "0:30-1:00", "quality": "worst"}]"#,
+        BatchFormat::Json,
+    )
+    .unwrap();
+    assert_eq!(entries[0].url, "42");
+    assert_eq!(entries[1].options.quality, Some(Quality::Worst));
+    assert_eq!(entries[1].options.section, Some(Section { start: 30.0, end: Some(60.0) }));
+}
+
+#[test]
+fn json_times_may_be_seconds_or_timestamps() {
+    let entries = parse(
+        r#"[{"url": "42", "start": 90}, {"url": "43", "start": "1:30", "end": 150.5}, {"url": "44", "end": "90"}]"#,
+        BatchFormat::Json,
+    )
+    .unwrap();
+    assert_eq!(entries[0].options.section, Some(Section { start: 90.0, end: None }));
+    assert_eq!(entries[1].options.section, Some(Section { start: 90.0, end: Some(150.5) }));
+    assert_eq!(entries[2].options.section, Some(Section { start: 0.0, end: Some(90.0) }));
+
+    let report = errors(r#"[{"url": "42", "start": -5}, {"url": "43", "end": true}]"#, BatchFormat::Json);
+    assert_eq!(report.len(), 2, "{report:?}");
+    assert!(report[0].starts_with("entry 1: invalid time '-5'"), "{}", report[0]);
+    assert!(report[1].starts_with("entry 2: "), "{}", report[1]);
+}
+
+#[test]
+fn reports_every_bad_line() {
+    let report = errors(
+        "url,format,quality,start,end\n\
+         42,mp4,720p,,\n\
+         https://example.com/video/1,,,,\n\
+         43,avi,,,\n\
+         44,,sharp,,\n\
+         45,,,2:00,1:00\n",
+        BatchFormat::Csv,
+    );
+    assert_eq!(report.len(), 4, "{report:#?}");
+    assert!(report[0].starts_with("line 3: ") && report[0].contains("not a parti.com address"), "{}", report[0]);
+    assert!(report[1].starts_with("line 4: unknown format 'avi'"), "{}", report[1]);
+    assert!(report[2].starts_with("line 5: Invalid quality 'sharp'"), "{}", report[2]);
+    assert!(report[3].starts_with("line 6: "), "{}", report[3]);
+}
+
+#[test]
+fn reports_unknown_columns_and_json_fields() {
+    let report = errors("url,resolution\n42,720\n", BatchFormat::Csv);
+    assert!(report[0].starts_with("line 1: ") && report[0].contains("unknown: resolution"), "{report:?}");
+
+    let report = errors(r#"["42", {"url": "43", "fromat": "mkv"}, 7]"#, BatchFormat::Json);
+    assert_eq!(report.len(), 2, "{report:?}");
+    assert!(report[0].starts_with("entry 2: ") && report[0].contains("fromat"), "{}", report[0]);
+    assert!(report[1].starts_with("entry 3: "), "{}", report[1]);
+}
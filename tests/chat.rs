@@ -0,0 +1,111 @@
+mod common;
+
+use parti_vod_downloader::chat::{parse_chat_messages, render_chat, save_chat, ChatFormat, ChatMessage};
+use parti_vod_downloader::{http_client, Error};
+use serde_json::json;
+
+/// 2023-11-14T22:13:20Z
+const STREAM_START: i64 = 1_700_000_000;
+
+fn message(offset: f64, author: &str, text: &str) -> ChatMessage {
+    ChatMessage { offset, author: author.to_string(), text: text.to_string() }
+}
+
+#[test]
+fn timestamps_in_seconds_milliseconds_or_rfc3339_are_placed_on_the_stream() {
+    let chat = json!({"messages": [
+        {"created_at": "2023-11-14T22:13:50.250Z", "text": "rfc 3339", "display_name": "carol"},
+        {"ts": 1_700_000_020_500u64, "content": "milliseconds", "user": {"name": "bob"}},
+        {"timestamp": 1_700_000_010, "message": "seconds", "username": "alice"},
+        {"sent_at": "1700000040", "msg": "numeric string"},
+        {"offset": 5.0, "message": "already relative", "username": "dave"},
+        {"timestamp": 1_700_000_050, "message": "   "},
+        {"message": "no time at all"},
+    ]});
+    let messages = parse_chat_messages(&chat, STREAM_START);
+    let parsed: Vec<(f64, &str, &str)> =
+        messages.iter().map(|m| (m.offset, m.author.as_str(), m.text.as_str())).collect();
+    assert_eq!(
+        parsed,
+        [
+            (5.0, "dave", "already relative"),
+            (10.0, "alice", "seconds"),
+            (20.5, "bob", "milliseconds"),
+            (30.25, "carol", "rfc 3339"),
+            (40.0, "anonymous", "numeric string"),
+        ]
+    );
+}
+
+#[test]
+fn a_bare_array_is_read_too() {
+    let chat = json!([{"offset": 1.0, "message": "hi", "username": "alice"}]);
+    assert_eq!(parse_chat_messages(&chat, STREAM_START).len(), 1);
+}
+
+#[test]
+fn only_messages_inside_the_clip_are_kept_and_shifted_to_its_start() {
+    let messages = [message(5.0, "a", "before"), message(15.5, "b", "inside"), message(25.0, "c", "after")];
+    let vtt = render_chat(&messages, ChatFormat::WebVtt, 10.0, Some(20.0));
+    assert_eq!(vtt, "WEBVTT\n\n00:00:05.500 --> 00:00:11.500\n<v b>inside\n\n");
+
+    let ass = render_chat(&messages, ChatFormat::Ass, 10.0, None);
+    let dialogues: Vec<&str> = ass.lines().filter(|l| l.starts_with("Dialogue:")).collect();
+    assert_eq!(
+        dialogues,
+        [
+            "Dialogue: 0,0:00:05.50,0:00:11.50,Default,b,0,0,0,,b: inside",
+            "Dialogue: 0,0:00:15.00,0:00:21.00,Default,c,0,0,0,,c: after",
+        ]
+    );
+}
+
+#[test]
+fn vtt_escapes_markup_and_line_breaks() {
+    let messages = [message(0.0, "<b>eve</b>", "1 < 2 & 3 > 2\nsecond line")];
+    let vtt = render_chat(&messages, ChatFormat::WebVtt, 0.0, None);
+    assert!(
+        vtt.ends_with("<v &lt;b&gt;eve&lt;/b&gt;>1 &lt; 2 &amp; 3 &gt; 2 second line\n\n"),
+        "{vtt}"
+    );
+}
+
+#[test]
+fn ass_escapes_override_tags_commas_and_line_breaks() {
+    let messages = [message(0.0, "smith, john", "{\\b1}bold{\\b0}\nnext")];
+    let ass = render_chat(&messages, ChatFormat::Ass, 0.0, None);
+    let dialogue = ass.lines().find(|l| l.starts_with("Dialogue:")).unwrap();
+    assert_eq!(
+        dialogue,
+        "Dialogue: 0,0:00:00.00,0:00:06.00,Default,smith  john,0,0,0,,smith, john: (\\b1)bold(\\b0)\\Nnext"
+    );
+}
+
+/// Saves the chat served at `/chat` by a mock server with `routes`.
+fn save(test: &str, routes: common::Routes) -> parti_vod_downloader::Result<Option<usize>> {
+    let base = common::serve(routes);
+    let dir = common::output_dir(test);
+    let folder = dir.to_string_lossy();
+    let url = format!("{base}/chat");
+    save_chat(&http_client(), &url, STREAM_START, ChatFormat::WebVtt, (0.0, None), Some(&folder), "v")
+}
+
+#[test]
+fn missing_or_empty_chat_is_no_chat() {
+    assert!(matches!(save("chat_missing", common::Routes::default()), Ok(None)));
+    assert!(matches!(save("chat_empty", common::Routes::default().ok("/chat", r#"{"messages": []}"#)), Ok(None)));
+    let chat = r#"[{"offset": 1.0, "message": "hi", "username": "alice"}]"#;
+    assert!(matches!(save("chat_saved", common::Routes::default().ok("/chat", chat)), Ok(Some(1))));
+}
+
+#[test]
+fn failed_chat_requests_are_errors() {
+    let err = save("chat_500", common::Routes::default().status("/chat", 500)).unwrap_err();
+    assert!(matches!(err, Error::Http(_)), "{err:?}");
+    let err = save("chat_401", common::Routes::default().status("/chat", 401)).unwrap_err();
+    assert!(matches!(err, Error::AuthRequired { status: 401 }), "{err:?}");
+    let err = save("chat_html", common::Routes::default().ok("/chat", "<html>")).unwrap_err();
+    assert!(matches!(err, Error::Http(_)), "{err:?}");
+    let err = save("chat_object", common::Routes::default().ok("/chat", r#"{"error": "nope"}"#)).unwrap_err();
+    assert!(matches!(err, Error::InvalidChat(_)), "{err:?}");
+}
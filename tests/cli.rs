@@ -0,0 +1,45 @@
+use parti_vod_downloader::cli::parse_cli_args;
+
+fn parse(args: &[&str]) -> Result<String, String> {
+    parse_cli_args(args.iter().map(|a| a.to_string()).collect()).map(|cli| cli.options.output_format)
+}
+
+#[test]
+fn format_is_checked_while_parsing() {
+    assert_eq!(parse(&["--format", "MKV", "42"]), Ok("mkv".to_string()));
+    assert_eq!(parse(&["--format=mp3", "-f", "webm", "42"]), Ok("webm".to_string()));
+    let err = parse(&["--format", "avi", "42"]).unwrap_err();
+    assert!(err.starts_with("Unknown format 'avi' (expected one of ts, mp4"), "{err}");
+}
+
+#[test]
+fn tiny_rate_limits_are_rejected() {
+    let rate = |rate: &str| {
+        let args = ["--limit-rate", rate, "42"].iter().map(|a| a.to_string()).collect();
+        parse_cli_args(args).map(|cli| cli.rate_limit)
+    };
+    assert_eq!(rate("5M"), Ok(Some(5 << 20)));
+    assert_eq!(rate("16K"), Ok(Some(16 << 10)));
+    assert_eq!(rate("1").unwrap_err(), "Invalid rate '1' (the minimum is 16K)");
+    assert!(rate("0").is_err());
+}
+
+#[test]
+fn network_flags_are_kept_in_order_for_later() {
+    let args = ["--proxy", "socks5://127.0.0.1:1080", "-H", "X-One: 1", "--header=X-Two: 2", "--read-timeout", "5"];
+    let cli = parse_cli_args(args.iter().chain(&["42"]).map(|a| a.to_string()).collect()).unwrap();
+    let flags: Vec<(&str, &str)> = cli.network_flags.iter().map(|(f, v)| (f.as_str(), v.as_str())).collect();
+    assert_eq!(
+        flags,
+        [
+            ("--proxy", "socks5://127.0.0.1:1080"),
+            ("-H", "X-One: 1"),
+            ("--header", "X-Two: 2"),
+            ("--read-timeout", "5"),
+        ]
+    );
+
+    let bad = |args: &[&str]| parse_cli_args(args.iter().map(|a| a.to_string()).collect()).err();
+    assert_eq!(bad(&["-H", "no colon", "42"]).as_deref(), Some("Header 'no colon' must look like 'Name: value'"));
+    assert_eq!(bad(&["--connect-timeout", "soon", "42"]).as_deref(), Some("Invalid timeout 'soon'"));
+}
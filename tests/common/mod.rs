@@ -0,0 +1,175 @@
+//! A tiny HTTP server for the integration tests, serving canned responses by path.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use parti_vod_downloader::{Downloader, PartiResolver};
+
+/// A canned response, given only to requests carrying the `required` header line and
+/// none of the `refused` header names.
+#[derive(Clone)]
+struct Route {
+    status: u16,
+    body: Vec<u8>,
+    required: Option<String>,
+    refused: Vec<String>,
+    /// Requests still to be answered with a 503 before the route works.
+    failures: Arc<AtomicUsize>,
+}
+
+impl Route {
+    fn new(status: u16, body: Vec<u8>) -> Self {
+        Self { status, body, required: None, refused: Vec::new(), failures: Arc::default() }
+    }
+
+    /// Whether this request is one of the failures still to come, counting it off.
+    fn fails(&self) -> bool {
+        self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok()
+    }
+}
+
+/// Canned responses by request path (without the query string).
+#[derive(Clone, Default)]
+pub struct Routes(HashMap<String, Route>);
+
+impl Routes {
+    pub fn ok(mut self, path: &str, body: impl Into<Vec<u8>>) -> Self {
+        self.0.insert(path.to_string(), Route::new(200, body.into()));
+        self
+    }
+
+    pub fn status(mut self, path: &str, status: u16) -> Self {
+        self.0.insert(path.to_string(), Route::new(status, Vec::new()));
+        self
+    }
+
+    /// Answers `path` with a 401 unless the request has the header line `header`, e.g.
+    /// `Cookie: session=abc` (compared case-insensitively).
+    pub fn protect(mut self, path: &str, header: &str) -> Self {
+        if let Some(route) = self.0.get_mut(path) {
+            route.required = Some(header.to_ascii_lowercase());
+        }
+        self
+    }
+
+    /// Answers every route added so far with a 400 if the request has a `name` header at
+    /// all, for a host that must never see it.
+    pub fn refuse(mut self, name: &str) -> Self {
+        for route in self.0.values_mut() {
+            route.refused.push(format!("{}:", name.to_ascii_lowercase()));
+        }
+        self
+    }
+
+    /// Answers the first `failures` requests for `path` with a 503.
+    pub fn flaky(mut self, path: &str, failures: usize) -> Self {
+        if let Some(route) = self.0.get_mut(path) {
+            route.failures = Arc::new(AtomicUsize::new(failures));
+        }
+        self
+    }
+
+    pub fn remove(mut self, path: &str) -> Self {
+        self.0.remove(path);
+        self
+    }
+}
+
+/// Starts serving `routes` on a free local port and returns its base URL, e.g.
+/// `http://127.0.0.1:4321`. Unknown paths get a 404. It also works as a plain HTTP proxy,
+/// answering for any host. The server lives as long as the test
+/// process.
+pub fn serve(routes: Routes) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let mut headers = Vec::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).map(|n| n > 2).unwrap_or(false) {
+                headers.push(line.trim().to_ascii_lowercase());
+                line.clear();
+            }
+            let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+            // Requests sent through it as a proxy carry the whole URL.
+            let target = match target.strip_prefix("http://") {
+                Some(rest) => rest.find('/').map_or("/", |i| &rest[i..]),
+                None => target,
+            };
+            let path = target.split('?').next().unwrap_or(target);
+            let (status, body) = match routes.0.get(path) {
+                Some(route) if route.required.as_ref().is_some_and(|h| !headers.contains(h)) => (401, Vec::new()),
+                Some(route) if headers.iter().any(|h| route.refused.iter().any(|name| h.starts_with(name))) => {
+                    (400, Vec::new())
+                }
+                Some(route) if route.fails() => (503, Vec::new()),
+                Some(route) => (route.status, route.body.clone()),
+                None => (404, Vec::new()),
+            };
+            let head = format!(
+                "HTTP/1.1 {status} Mock\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).ok();
+            stream.write_all(&body).ok();
+        }
+    });
+    base
+}
+
+pub fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)).unwrap()
+}
+
+/// Bytes of fixture segment `n`, distinct per segment so the order can be checked.
+pub fn segment(n: usize) -> Vec<u8> {
+    format!("segment {n} ").repeat(100).into_bytes()
+}
+
+/// The API answer and playlists for video 42, with four 10 s segments and English subtitles.
+pub fn vod_routes() -> Routes {
+    let mut routes = Routes::default()
+        .ok("/parti_v2/profile/get_livestream_channel_info/recent/42", fixture("video.json"))
+        .ok("/thumbs/42.jpg", "thumbnail")
+        .ok("/vod/42/master.m3u8", fixture("vod/42/master.m3u8"))
+        .ok("/vod/42/720p/playlist.m3u8", fixture("vod/42/720p/playlist.m3u8"))
+        .ok("/vod/42/subs/en.m3u8", fixture("vod/42/subs/en.m3u8"))
+        .ok("/vod/42/subs/en0.vtt", fixture("vod/42/subs/en0.vtt"))
+        .ok("/vod/42/subs/en1.vtt", fixture("vod/42/subs/en1.vtt"));
+    for n in 0..4 {
+        routes = routes.ok(&format!("/vod/42/720p/seg{n}.ts"), segment(n));
+    }
+    routes
+}
+
+/// A resolver pointing both the API and the watch host at the mock server.
+pub fn resolver(base: &str) -> PartiResolver {
+    PartiResolver {
+        api_base: format!("{base}/parti_v2/"),
+        watch_base: format!("{base}/"),
+    }
+}
+
+pub fn downloader(base: &str) -> Downloader {
+    Downloader::with_resolver(resolver(base))
+}
+
+/// An empty folder for one test's output.
+pub fn output_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("parti_vod_test_{}_{test}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
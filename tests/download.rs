@@ -0,0 +1,646 @@
+mod common;
+
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use common::Routes;
+use parti_vod_downloader::playlist::Quality;
+use parti_vod_downloader::subtitles::{download_subtitles, Cue, SubtitleFormat};
+use parti_vod_downloader::{
+    http_client, parse_section, DownloadControl, DownloadEvent, DownloadOptions, DownloadProgress, Downloader, Error,
+    HlsResolver, Overwrite, RateLimiter, SplitBy, MIN_RATE_LIMIT,
+};
+
+const URL: &str = "https://parti.com/video/42";
+
+fn options(test: &str) -> (DownloadOptions, PathBuf) {
+    let dir = common::output_dir(test);
+    let options = DownloadOptions {
+        output_format: "ts".to_string(),
+        download_folder: Some(dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    (options, dir)
+}
+
+fn finished_paths(events: &Receiver<DownloadEvent>) -> Vec<PathBuf> {
+    match events.try_iter().last() {
+        Some(DownloadEvent::Finished { paths, .. }) => paths,
+        other => panic!("expected Finished, got {other:?}"),
+    }
+}
+
+fn segments(range: std::ops::Range<usize>) -> Vec<u8> {
+    range.flat_map(common::segment).collect()
+}
+
+#[test]
+fn downloads_every_segment_in_order() {
+    let base = common::serve(common::vod_routes());
+    let (options, dir) = options("full");
+    let (tx, rx) = std::sync::mpsc::channel();
+    common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap();
+
+    let events: Vec<DownloadEvent> = rx.try_iter().collect();
+    assert!(matches!(&events[0], DownloadEvent::Resolved(info) if info.title == "Test Stream: Day 1"));
+    let done = events.iter().filter(|e| matches!(e, DownloadEvent::SegmentDone { .. })).count();
+    assert_eq!(done, 4);
+    let Some(DownloadEvent::Finished { paths, .. }) = events.last() else {
+        panic!("expected Finished, got {:?}", events.last());
+    };
+    assert_eq!(paths, &[dir.join("Test_Stream_Day_1_2023-11-14.ts")]);
+    assert_eq!(std::fs::read(&paths[0]).unwrap(), segments(0..4));
+}
+
+#[test]
+fn section_fetches_only_covering_segments() {
+    let base = common::serve(common::vod_routes());
+    let (mut options, _dir) = options("section");
+    options.section = Some(parse_section("0:12-0:25").unwrap());
+    let (tx, rx) = std::sync::mpsc::channel();
+    common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap();
+
+    let paths = finished_paths(&rx);
+    assert_eq!(std::fs::read(&paths[0]).unwrap(), segments(1..3));
+}
+
+#[test]
+fn split_by_duration_writes_parts() {
+    let base = common::serve(common::vod_routes());
+    let (mut options, dir) = options("split");
+    options.split = Some(SplitBy::Duration(20.0));
+    let (tx, rx) = std::sync::mpsc::channel();
+    common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap();
+
+    let paths = finished_paths(&rx);
+    assert_eq!(
+        paths,
+        [
+            dir.join("Test_Stream_Day_1_2023-11-14_part01.ts"),
+            dir.join("Test_Stream_Day_1_2023-11-14_part02.ts"),
+        ]
+    );
+    assert_eq!(std::fs::read(&paths[0]).unwrap(), segments(0..2));
+    assert_eq!(std::fs::read(&paths[1]).unwrap(), segments(2..4));
+}
+
+#[test]
+fn subtitles_are_stitched_with_corrected_times() {
+    let base = common::serve(common::vod_routes());
+    let (mut options, dir) = options("subs");
+    options.subtitles = Some(SubtitleFormat::Srt);
+    let (tx, _rx) = std::sync::mpsc::channel();
+    common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap();
+
+    let srt = std::fs::read_to_string(dir.join("Test_Stream_Day_1_2023-11-14.en.srt")).unwrap();
+    assert!(srt.starts_with("1\n00:00:01,000 --> 00:00:04,000\nHello and welcome\n"), "{srt}");
+    assert!(srt.contains("3\n00:00:25,000 --> 00:00:28,000\nAfter the cut\n"), "{srt}");
+}
+
+#[test]
+fn clipped_subtitles_keep_the_playlist_timeline() {
+    let base = common::serve(common::vod_routes());
+    let playlist = format!("{base}/vod/42/subs/en.m3u8");
+    // Only the second subtitle segment is in the clip; its cue is 25 s into the recording.
+    let cues = download_subtitles(&http_client(), &playlist, (20.0, Some(40.0))).unwrap();
+    assert_eq!(cues, vec![Cue { start: 5.0, end: 8.0, text: "After the cut".to_string() }]);
+}
+
+#[test]
+fn failed_subtitle_segments_are_errors() {
+    for routes in [
+        common::vod_routes().status("/vod/42/subs/en1.vtt", 404),
+        common::vod_routes().flaky("/vod/42/subs/en1.vtt", 1),
+    ] {
+        let base = common::serve(routes);
+        let playlist = format!("{base}/vod/42/subs/en.m3u8");
+        let err = download_subtitles(&http_client(), &playlist, (0.0, None)).unwrap_err();
+        assert!(matches!(err, Error::Http(_)), "{err:?}");
+    }
+}
+
+#[test]
+fn cancelled_download_stops_with_aborted() {
+    let base = common::serve(common::vod_routes());
+    let (options, _dir) = options("cancel");
+    let control = DownloadControl::default();
+    control.cancel();
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let err = common::downloader(&base).download(URL, &options, &control, &tx).unwrap_err();
+    assert!(matches!(err, Error::Aborted), "{err:?}");
+}
+
+#[test]
+fn rate_limiter_paces_reads_across_clones() {
+    // 100 KB at 100 KB/s, read in chunks by two downloads sharing the limit.
+    let limiter = RateLimiter::new(Some(100_000));
+    let started = Instant::now();
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let limiter = limiter.clone();
+            std::thread::spawn(move || (0..5).for_each(|_| limiter.take(10_000, &DownloadControl::default())))
+        })
+        .collect();
+    readers.into_iter().for_each(|reader| reader.join().unwrap());
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(900), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+
+    // Lifting the limit takes effect right away.
+    limiter.set_limit(None);
+    let started = Instant::now();
+    limiter.take(100_000_000, &DownloadControl::default());
+    assert!(started.elapsed() < Duration::from_millis(100));
+    assert_eq!(limiter.limit(), None);
+}
+
+#[test]
+fn long_rate_limit_waits_end_early() {
+    // Each of these reads would otherwise owe 100 seconds.
+    let limiter = RateLimiter::new(Some(MIN_RATE_LIMIT));
+    let control = DownloadControl::default();
+    let waiting = {
+        let (limiter, control) = (limiter.clone(), control.clone());
+        std::thread::spawn(move || limiter.take(100 * MIN_RATE_LIMIT as usize, &control))
+    };
+    std::thread::sleep(Duration::from_millis(100));
+    let started = Instant::now();
+    limiter.set_limit(None);
+    waiting.join().unwrap();
+    assert!(started.elapsed() < Duration::from_millis(300), "{:?}", started.elapsed());
+
+    limiter.set_limit(Some(MIN_RATE_LIMIT));
+    let waiting = {
+        let (limiter, control) = (limiter.clone(), control.clone());
+        std::thread::spawn(move || limiter.take(100 * MIN_RATE_LIMIT as usize, &control))
+    };
+    std::thread::sleep(Duration::from_millis(100));
+    let started = Instant::now();
+    control.cancel();
+    waiting.join().unwrap();
+    assert!(started.elapsed() < Duration::from_millis(300), "{:?}", started.elapsed());
+}
+
+fn segment_done(index: usize, bytes: u64, duration: f64, remaining: f64) -> DownloadEvent {
+    DownloadEvent::SegmentDone { index, total: 4, bytes, duration, remaining }
+}
+
+#[test]
+fn progress_has_no_speed_or_eta_right_after_starting() {
+    let mut progress = DownloadProgress::default();
+    assert_eq!((progress.speed(), progress.eta()), (0.0, None));
+    // A segment with no time elapsed would otherwise be an infinite speed.
+    progress.apply(segment_done(0, 1_000_000, 10.0, 30.0));
+    assert_eq!((progress.speed(), progress.eta()), (0.0, None));
+    assert_eq!(progress.transfer_summary().as_deref(), Some("976.6 KB"));
+}
+
+#[test]
+fn eta_sizes_the_rest_by_seconds_of_video() {
+    let mut progress = DownloadProgress::default();
+    // 1 MB for 10 s of video, then 1 MB for only 2 s: 2 MB per 12 s.
+    progress.apply(segment_done(0, 1_000_000, 10.0, 30.0));
+    std::thread::sleep(Duration::from_millis(600));
+    progress.apply(segment_done(1, 1_000_000, 2.0, 20.0));
+    let speed = progress.speed();
+    assert!(speed > 0.0);
+    // 20 s left at 2 MB per 12 s, rather than 1 MB per segment.
+    let remaining_bytes = progress.eta().unwrap() * speed;
+    let expected = 20.0 * 2_000_000.0 / 12.0;
+    assert!((remaining_bytes - expected).abs() < expected * 0.05, "{remaining_bytes}");
+}
+
+#[test]
+fn paused_download_resumes_from_its_checkpoint() {
+    let base = common::serve(common::vod_routes());
+    let (options, dir) = options("resume");
+    let output = dir.join("Test_Stream_Day_1_2023-11-14.ts");
+    let downloader = common::downloader(&base);
+    // Slow enough that the pause lands well before the last segment.
+    downloader.limiter.set_limit(Some(4000));
+    let control = DownloadControl::default();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let worker = {
+        let (options, control) = (options.clone(), control.clone());
+        std::thread::spawn(move || downloader.download(URL, &options, &control, &tx))
+    };
+    // Pause after the first segment, then give up on the paused download like a closed app.
+    for event in rx.iter() {
+        match event {
+            DownloadEvent::SegmentDone { .. } => control.pause(),
+            DownloadEvent::Status(status) if status.starts_with("Paused at") => break,
+            _ => {}
+        }
+    }
+    control.cancel();
+    assert!(matches!(worker.join().unwrap(), Err(Error::Aborted)));
+
+    let checkpoint = control.checkpoint().unwrap();
+    assert!((1..4).contains(&checkpoint.next_segment), "{checkpoint:?}");
+    assert_eq!(checkpoint.parts, vec![output.clone()]);
+    assert!(checkpoint.is_usable(4));
+    assert!(!checkpoint.is_usable(5), "a changed playlist starts over");
+    // Bytes of a segment that was being written when the app closed are dropped.
+    let mut file = std::fs::OpenOptions::new().append(true).open(&output).unwrap();
+    std::io::Write::write_all(&mut file, b"half a segment").unwrap();
+    drop(file);
+
+    // The partial file would otherwise make the default Rename policy pick `_2`.
+    let next_segment = checkpoint.next_segment;
+    let control = DownloadControl::with_checkpoint(Some(checkpoint));
+    let (tx, rx) = std::sync::mpsc::channel();
+    common::downloader(&base).download(URL, &options, &control, &tx).unwrap();
+    let events: Vec<DownloadEvent> = rx.try_iter().collect();
+    let fetched: Vec<usize> = events
+        .iter()
+        .filter_map(|e| match e {
+            DownloadEvent::SegmentDone { index, .. } => Some(*index),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(fetched, (next_segment..4).collect::<Vec<_>>());
+    assert!(matches!(events.last(), Some(DownloadEvent::Finished { paths, .. }) if paths == &vec![output.clone()]));
+    assert_eq!(std::fs::read(&output).unwrap(), segments(0..4));
+}
+
+#[test]
+fn cancelling_after_the_last_segment_is_not_finished() {
+    let base = common::serve(common::vod_routes());
+    let (options, _dir) = options("cancel_last");
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let downloaded = DownloadControl::default();
+    common::downloader(&base).download(URL, &options, &downloaded, &tx).unwrap();
+
+    // Every segment is already there, so only the conversion would be left.
+    let control = DownloadControl::with_checkpoint(downloaded.checkpoint());
+    control.cancel();
+    let options = DownloadOptions { output_format: "mp4".to_string(), ..options };
+    let (tx, rx) = std::sync::mpsc::channel();
+    let err = common::downloader(&base).download(URL, &options, &control, &tx).unwrap_err();
+    assert!(matches!(err, Error::Aborted), "{err:?}");
+    assert!(!rx.try_iter().any(|e| matches!(e, DownloadEvent::Finished { .. })));
+}
+
+#[test]
+fn failed_segment_reports_its_index() {
+    let base = common::serve(common::vod_routes().remove("/vod/42/720p/seg2.ts"));
+    let (options, _dir) = options("segment_failed");
+    let control = DownloadControl::default();
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let err = common::downloader(&base).download(URL, &options, &control, &tx).unwrap_err();
+    assert!(matches!(err, Error::SegmentFailed { index: 2, .. }), "{err:?}");
+    // The first two segments are kept for a resume.
+    assert_eq!(control.checkpoint().unwrap().next_segment, 2);
+}
+
+#[test]
+fn failing_requests_are_retried() {
+    let routes = common::vod_routes()
+        .flaky("/vod/42/master.m3u8", 1)
+        .flaky("/vod/42/720p/playlist.m3u8", 1)
+        .flaky("/vod/42/720p/seg1.ts", 2);
+    let base = common::serve(routes);
+    let (options, dir) = options("retried");
+    let (tx, _rx) = std::sync::mpsc::channel();
+    common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap();
+    let expected: Vec<u8> = (0..4).flat_map(common::segment).collect();
+    assert_eq!(std::fs::read(dir.join("Test_Stream_Day_1_2023-11-14.ts")).unwrap(), expected);
+}
+
+#[test]
+fn segments_that_keep_failing_give_up() {
+    let base = common::serve(common::vod_routes().flaky("/vod/42/720p/seg1.ts", 10));
+    let (options, _dir) = options("retries_exhausted");
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let err = common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap_err();
+    assert!(matches!(err, Error::SegmentFailed { index: 1, .. }), "{err:?}");
+    assert!(err.is_retryable());
+}
+
+#[test]
+fn missing_variant_playlist_is_reported() {
+    let base = common::serve(common::vod_routes().remove("/vod/42/720p/playlist.m3u8"));
+    let (options, _dir) = options("empty_variant");
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let err = common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap_err();
+    assert!(matches!(err, Error::EmptyVariant), "{err:?}");
+}
+
+fn hls_routes() -> Routes {
+    common::vod_routes()
+        .ok("/shows/3/master", common::fixture("hls/master.m3u8"))
+        .ok("/shows/3/low/index.m3u8", common::fixture("hls/media.m3u8"))
+        .ok("/shows/3/high/index.m3u8", common::fixture("vod/42/720p/playlist.m3u8"))
+}
+
+#[test]
+fn generic_hls_takes_metadata_from_the_playlist() {
+    let base = common::serve(hls_routes());
+    let (options, dir) = options("hls");
+    let (tx, rx) = std::sync::mpsc::channel();
+    Downloader::with_resolver(HlsResolver)
+        .download(&format!("{base}/shows/3/master"), &options, &DownloadControl::default(), &tx)
+        .unwrap();
+    let paths = finished_paths(&rx);
+    assert_eq!(paths, [dir.join("Morning_Show_Episode_3_2024-03-05.ts")]);
+    assert_eq!(std::fs::read(&paths[0]).unwrap(), segments(0..2));
+}
+
+#[test]
+fn user_supplied_title_and_date_name_the_output() {
+    let base = common::serve(hls_routes());
+    let (options, dir) = options("named");
+    let options = DownloadOptions {
+        title: Some("My Clip".to_string()),
+        date: Some("2020-01-01".to_string()),
+        ..options
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    common::downloader(&base)
+        .download(&format!("{base}/shows/3/low/index.m3u8"), &options, &DownloadControl::default(), &tx)
+        .unwrap();
+    assert_eq!(finished_paths(&rx), [dir.join("My_Clip_2020-01-01.ts")]);
+}
+
+#[test]
+fn quality_picks_the_variant() {
+    let high = (0..4).map(|n| format!("#EXTINF:10.0,\n/vod/42/720p/seg{n}.ts\n")).collect::<String>();
+    let base = common::serve(hls_routes().ok("/shows/3/high/index.m3u8", format!("#EXTM3U\n{high}#EXT-X-ENDLIST\n")));
+    for (quality, segment_count) in [(Quality::Best, 4), (Quality::Height(480), 2), (Quality::Worst, 2)] {
+        let (options, _) = options(&format!("quality_{quality}"));
+        let options = DownloadOptions { quality: Some(quality), ..options };
+        let (tx, rx) = std::sync::mpsc::channel();
+        Downloader::with_resolver(HlsResolver)
+            .download(&format!("{base}/shows/3/master"), &options, &DownloadControl::default(), &tx)
+            .unwrap();
+        let done = rx.try_iter().filter(|e| matches!(e, DownloadEvent::SegmentDone { .. })).count();
+        assert_eq!(done, segment_count, "{quality}");
+    }
+}
+
+#[test]
+fn existing_output_is_renamed_skipped_or_overwritten() {
+    let base = common::serve(common::vod_routes());
+    let (options, dir) = options("overwrite");
+    let existing = dir.join("Test_Stream_Day_1_2023-11-14.ts");
+    std::fs::write(&existing, "earlier download").unwrap();
+    let run = |overwrite: Overwrite| {
+        let options = DownloadOptions { overwrite, ..options.clone() };
+        let (tx, rx) = std::sync::mpsc::channel();
+        common::downloader(&base)
+            .download(URL, &options, &DownloadControl::default(), &tx)
+            .unwrap();
+        let events: Vec<DownloadEvent> = rx.try_iter().collect();
+        let fetched = events.iter().filter(|e| matches!(e, DownloadEvent::SegmentDone { .. })).count();
+        let Some(DownloadEvent::Finished { paths, .. }) = events.last() else {
+            panic!("expected Finished, got {:?}", events.last());
+        };
+        (paths.clone(), fetched)
+    };
+
+    assert_eq!(run(Overwrite::Skip), (vec![existing.clone()], 0));
+    assert_eq!(std::fs::read(&existing).unwrap(), b"earlier download");
+    assert_eq!(run(Overwrite::Rename), (vec![dir.join("Test_Stream_Day_1_2023-11-14_2.ts")], 4));
+    assert_eq!(run(Overwrite::Rename), (vec![dir.join("Test_Stream_Day_1_2023-11-14_3.ts")], 4));
+    assert_eq!(std::fs::read(&existing).unwrap(), b"earlier download");
+    assert_eq!(run(Overwrite::Overwrite), (vec![existing.clone()], 4));
+    assert_eq!(std::fs::read(&existing).unwrap(), segments(0..4));
+}
+
+#[test]
+fn existing_raw_download_counts_as_taken() {
+    let base = common::serve(common::vod_routes());
+    let (options, dir) = options("overwrite_raw");
+    // A converted download would first write its raw .ts under the same name.
+    let earlier = dir.join("Test_Stream_Day_1_2023-11-14.ts");
+    let earlier_part = dir.join("Test_Stream_Day_1_2023-11-14_part01.ts");
+    for (path, split) in [(&earlier, None), (&earlier_part, Some(SplitBy::Duration(20.0)))] {
+        std::fs::write(path, "earlier download").unwrap();
+        let options = DownloadOptions {
+            output_format: "mp4".to_string(),
+            overwrite: Overwrite::Skip,
+            split,
+            ..options.clone()
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        common::downloader(&base)
+            .download(URL, &options, &DownloadControl::default(), &tx)
+            .unwrap();
+        assert_eq!(finished_paths(&rx), vec![path.to_path_buf()]);
+        assert_eq!(std::fs::read(path).unwrap(), b"earlier download");
+    }
+}
+
+#[test]
+fn overwriting_split_output_removes_the_old_higher_parts() {
+    let base = common::serve(common::vod_routes());
+    let (options, dir) = options("overwrite_parts");
+    let part = |n: usize| dir.join(format!("Test_Stream_Day_1_2023-11-14_part{n:02}.ts"));
+    for n in 1..=3 {
+        std::fs::write(part(n), "earlier download").unwrap();
+    }
+    let options = DownloadOptions {
+        overwrite: Overwrite::Overwrite,
+        split: Some(SplitBy::Duration(20.0)),
+        section: parse_section("0-0:15").ok(),
+        ..options
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap();
+    assert_eq!(finished_paths(&rx), vec![part(1)]);
+    assert_eq!(std::fs::read(part(1)).unwrap(), segments(0..2));
+    assert!(!part(2).exists() && !part(3).exists());
+}
+
+#[test]
+fn download_failing_before_its_first_segment_leaves_no_file() {
+    let broken = common::serve(common::vod_routes().remove("/vod/42/720p/seg0.ts"));
+    let (options, dir) = options("failed_early");
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let err = common::downloader(&broken)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap_err();
+    assert!(matches!(err, Error::SegmentFailed { index: 0, .. }), "{err:?}");
+    assert!(std::fs::read_dir(&dir).unwrap().next().is_none(), "nothing should be left");
+
+    // So trying again keeps the name instead of renaming around an empty file.
+    let base = common::serve(common::vod_routes());
+    let (tx, rx) = std::sync::mpsc::channel();
+    common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap();
+    assert_eq!(finished_paths(&rx), vec![dir.join("Test_Stream_Day_1_2023-11-14.ts")]);
+}
+
+#[test]
+fn refuses_downloads_larger_than_the_free_space() {
+    // 40 s at this bandwidth is far more than any disk holds.
+    let master = "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=9000000000000000000\n720p/playlist.m3u8\n";
+    let base = common::serve(common::vod_routes().ok("/vod/42/master.m3u8", master));
+    let (options, dir) = options("free_space");
+    let (tx, rx) = std::sync::mpsc::channel();
+    let err = common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap_err();
+    assert!(matches!(err, Error::NotEnoughSpace { .. }), "{err:?}");
+    assert!(std::fs::read_dir(&dir).unwrap().next().is_none(), "nothing should be written");
+
+    let options = DownloadOptions { ignore_free_space: true, ..options };
+    common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap();
+    assert_eq!(finished_paths(&rx).len(), 1);
+}
+
+#[test]
+fn free_space_is_estimated_from_a_segment_without_a_bandwidth() {
+    // No BANDWIDTH anywhere, and the first segment is so short that its size makes the
+    // whole recording look huge.
+    let media = "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:0.000000001,\nseg0.ts\n#EXTINF:10.0,\nseg1.ts\n\
+                 #EXTINF:10.0,\nseg2.ts\n#EXT-X-ENDLIST\n";
+    let base = common::serve(common::vod_routes().ok("/vod/42/master.m3u8", media.replace("seg", "720p/seg")));
+    let (options, dir) = options("free_space_estimated");
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let err = common::downloader(&base)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap_err();
+    assert!(matches!(err, Error::NotEnoughSpace { .. }), "{err:?}");
+    assert!(std::fs::read_dir(&dir).unwrap().next().is_none(), "nothing should be written");
+}
+
+#[test]
+fn warns_when_the_download_size_cannot_be_estimated() {
+    let media = "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10.0,\n720p/seg0.ts\n#EXT-X-ENDLIST\n";
+    let routes = common::vod_routes().ok("/vod/42/master.m3u8", media).remove("/vod/42/720p/seg0.ts");
+    let base = common::serve(routes);
+    let (options, _dir) = options("free_space_unknown");
+    let (tx, rx) = std::sync::mpsc::channel();
+    let result = common::downloader(&base).download(URL, &options, &DownloadControl::default(), &tx);
+    assert!(matches!(result, Err(Error::SegmentFailed { index: 0, .. })), "{result:?}");
+    let warned = rx
+        .try_iter()
+        .any(|event| matches!(event, DownloadEvent::Status(status) if status.contains("free space not checked")));
+    assert!(warned);
+}
+
+/// A master with a video variant and three audio renditions in one group, the second and third both
+/// marked default. Each rendition's playlist has its own number of segments.
+fn audio_rendition_routes() -> Routes {
+    let master = "#EXTM3U\n\
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Commentary\",LANGUAGE=\"en\",URI=\"audio/commentary.m3u8\"\n\
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Main\",LANGUAGE=\"en\",DEFAULT=YES,URI=\"audio/main.m3u8\"\n\
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Backup\",DEFAULT=YES,URI=\"audio/backup.m3u8\"\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=2000000,RESOLUTION=1280x720,AUDIO=\"aud\"\n\
+        720p/playlist.m3u8\n";
+    let audio = |count: usize| {
+        let segments: String = (0..count).map(|n| format!("#EXTINF:10.0,\n../720p/seg{n}.ts\n")).collect();
+        format!("#EXTM3U\n#EXT-X-TARGETDURATION:10\n{segments}#EXT-X-ENDLIST\n")
+    };
+    common::vod_routes()
+        .ok("/vod/42/master.m3u8", master)
+        .ok("/vod/42/audio/commentary.m3u8", audio(1))
+        .ok("/vod/42/audio/main.m3u8", audio(2))
+        .ok("/vod/42/audio/backup.m3u8", audio(3))
+}
+
+/// Starts an mp3 download that is paused before its first segment and returns its events up
+/// to there, which shows the playlist it picked without converting anything.
+fn audio_only_events(test: &str, routes: Routes) -> Vec<DownloadEvent> {
+    let base = common::serve(routes);
+    let (options, _dir) = options(test);
+    let options = DownloadOptions { output_format: "mp3".to_string(), ..options };
+    let control = DownloadControl::default();
+    control.pause();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let worker = {
+        let control = control.clone();
+        std::thread::spawn(move || common::downloader(&base).download(URL, &options, &control, &tx))
+    };
+    let mut events = Vec::new();
+    for event in rx.iter() {
+        let paused = matches!(&event, DownloadEvent::Status(status) if status.starts_with("Paused at"));
+        events.push(event);
+        if paused {
+            break;
+        }
+    }
+    control.cancel();
+    let result = worker.join().unwrap();
+    assert!(matches!(result, Err(Error::Aborted)), "{result:?}");
+    events
+}
+
+fn downloading_status(events: &[DownloadEvent]) -> Option<&str> {
+    events.iter().find_map(|event| match event {
+        DownloadEvent::Status(status) if status.starts_with("Downloading") => Some(status.as_str()),
+        _ => None,
+    })
+}
+
+#[test]
+fn audio_only_output_fetches_the_first_default_audio_rendition() {
+    let events = audio_only_events("audio_default", audio_rendition_routes());
+    assert_eq!(downloading_status(&events), Some("Downloading 2 segments..."));
+}
+
+#[test]
+fn audio_only_output_falls_back_to_the_first_rendition_of_the_group() {
+    let routes = audio_rendition_routes().ok(
+        "/vod/42/master.m3u8",
+        "#EXTM3U\n\
+         #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"other\",NAME=\"Main\",DEFAULT=YES,URI=\"audio/main.m3u8\"\n\
+         #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Backup\",URI=\"audio/backup.m3u8\"\n\
+         #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Commentary\",URI=\"audio/commentary.m3u8\"\n\
+         #EXT-X-STREAM-INF:BANDWIDTH=2000000,AUDIO=\"aud\"\n\
+         720p/playlist.m3u8\n",
+    );
+    let events = audio_only_events("audio_first", routes);
+    assert_eq!(downloading_status(&events), Some("Downloading 3 segments..."));
+}
+
+#[test]
+fn audio_only_output_without_renditions_uses_the_lowest_bandwidth_variant() {
+    let routes = common::vod_routes()
+        .ok(
+            "/vod/42/master.m3u8",
+            "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=2000000\n720p/playlist.m3u8\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=100000\naudio/main.m3u8\n",
+        )
+        .ok(
+            "/vod/42/audio/main.m3u8",
+            "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10.0,\n../720p/seg0.ts\n#EXT-X-ENDLIST\n",
+        );
+    let events = audio_only_events("audio_variant", routes);
+    assert_eq!(downloading_status(&events), Some("Downloading 1 segments..."));
+}
+
+#[test]
+fn audio_only_free_space_check_ignores_the_video_bandwidth() {
+    // Sized at the video variant's bandwidth, this would never fit on any disk.
+    let routes = audio_rendition_routes().ok(
+        "/vod/42/master.m3u8",
+        "#EXTM3U\n\
+         #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Main\",DEFAULT=YES,URI=\"audio/main.m3u8\"\n\
+         #EXT-X-STREAM-INF:BANDWIDTH=9000000000000000000,AUDIO=\"aud\"\n\
+         720p/playlist.m3u8\n",
+    );
+    let events = audio_only_events("audio_free_space", routes);
+    assert_eq!(downloading_status(&events), Some("Downloading 2 segments..."));
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use parti_vod_downloader::history::write_csv;
+use parti_vod_downloader::{DownloadOptions, DownloadQueue, History, HistoryResult};
+
+/// Runs `urls` (the first two segments of each) through a queue against the mock
+/// server and returns its history. Only video 42 exists there.
+fn run_queue(test: &str, urls: &[&str]) -> History {
+    let base = common::serve(common::vod_routes());
+    let dir = common::output_dir(test);
+    let options = DownloadOptions {
+        download_folder: Some(dir.to_string_lossy().into_owned()),
+        section: parti_vod_downloader::parse_section("0-0:15").ok(),
+        ..Default::default()
+    };
+    let mut queue = DownloadQueue::with_downloader(1, common::downloader(&base));
+    for url in urls {
+        queue.add(url.to_string(), options.clone());
+    }
+    while queue.is_active() {
+        queue.tick();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    queue.tick();
+    queue.history
+}
+
+#[test]
+fn queue_records_finished_and_failed_downloads() {
+    let history = run_queue("history_queue", &["https://parti.com/video/42", "https://parti.com/video/7"]);
+    let [finished, failed] = &history.entries[..] else {
+        panic!("expected two entries, got {:?}", history.entries);
+    };
+    assert_eq!(finished.result, HistoryResult::Finished);
+    assert_eq!(finished.video_id, "42");
+    assert_eq!(finished.title, "Test Stream: Day 1");
+    assert_eq!(finished.format, "ts");
+    assert_eq!(finished.paths.len(), 1);
+    assert_eq!(finished.size, std::fs::metadata(&finished.paths[0]).unwrap().len());
+    assert_eq!(failed.result, HistoryResult::Failed);
+    assert_eq!(failed.url, "https://parti.com/video/7");
+    assert!(failed.error.is_some());
+    assert!(failed.paths.is_empty());
+}
+
+#[test]
+fn search_filters_by_text_and_result() {
+    let history = run_queue("history_search", &["https://parti.com/video/42", "https://parti.com/video/7"]);
+    assert_eq!(history.search("", None), [1, 0]);
+    assert_eq!(history.search("day 1", None), [0]);
+    assert_eq!(history.search("video/7", None), [1]);
+    assert_eq!(history.search("", Some(HistoryResult::Failed)), [1]);
+    assert!(history.search("day 1", Some(HistoryResult::Cancelled)).is_empty());
+}
+
+#[test]
+fn history_is_saved_and_exported() {
+    let recorded = run_queue("history_saved", &["https://parti.com/video/42"]);
+    let path = common::output_dir("history_file").join("history.json");
+    let mut history = History::open(path.clone());
+    history.record(recorded.entries[0].clone());
+    let reopened = History::open(path);
+    assert_eq!(reopened.entries.len(), 1);
+    assert_eq!(reopened.entries[0].options.section, recorded.entries[0].options.section);
+
+    let mut csv = Vec::new();
+    write_csv(&reopened.entries, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "time,result,title,video_id,url,format,size,paths,error");
+    assert!(lines[1].contains(",finished,Test Stream: Day 1,42,https://parti.com/video/42,ts,"), "{}", lines[1]);
+}
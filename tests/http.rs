@@ -0,0 +1,240 @@
+mod common;
+
+use parti_vod_downloader::{
+    build_client, http_client, AuthSettings, DownloadControl, DownloadOptions, Error, HttpSettings, ProxySettings,
+    Resolver,
+};
+
+fn proxied(url: &str, no_proxy: &str) -> HttpSettings {
+    HttpSettings {
+        proxy: ProxySettings { url: url.to_string(), no_proxy: no_proxy.to_string(), ..Default::default() },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn requests_go_through_the_proxy() {
+    let proxy = common::serve(common::vod_routes());
+    let client = build_client(&proxied(&proxy, "")).unwrap();
+    let info = common::resolver("http://parti.invalid")
+        .resolve(&client, "https://parti.com/video/42")
+        .unwrap();
+    assert_eq!(info.title, "Test Stream: Day 1");
+    assert_eq!(info.playlist_url, "http://parti.invalid/vod/42/master.m3u8");
+}
+
+#[test]
+fn no_proxy_hosts_connect_directly() {
+    let base = common::serve(common::vod_routes());
+    // Nothing listens on port 9, so only a direct connection can succeed.
+    let client = build_client(&proxied("http://127.0.0.1:9", "127.0.0.1")).unwrap();
+    let info = common::resolver(&base).resolve(&client, "https://parti.com/video/42").unwrap();
+    assert_eq!(info.video_id, "42");
+}
+
+#[test]
+fn rejects_unsupported_proxy_schemes() {
+    let result = build_client(&proxied("ftp://proxy.example:21", ""));
+    assert!(matches!(result, Err(Error::Settings(_))), "{result:?}");
+}
+
+const API_PATH: &str = "/parti_v2/profile/get_livestream_channel_info/recent/42";
+
+fn with_auth(auth: AuthSettings) -> HttpSettings {
+    HttpSettings { auth, ..Default::default() }
+}
+
+#[test]
+fn members_only_video_needs_credentials() {
+    let base = common::serve(common::vod_routes().protect(API_PATH, "Authorization: Bearer secret"));
+    let result = common::resolver(&base).resolve(&http_client(), "https://parti.com/video/42");
+    assert!(matches!(result, Err(Error::AuthRequired { status: 401 })), "{result:?}");
+}
+
+fn with_token() -> HttpSettings {
+    with_auth(AuthSettings { bearer_token: "secret".to_string(), ..Default::default() })
+}
+
+#[test]
+fn bearer_token_is_sent() {
+    let base = common::serve(common::vod_routes().protect(API_PATH, "Authorization: Bearer secret"));
+    let mut downloader = common::downloader(&base);
+    downloader.set_http(with_token()).unwrap();
+    let info = downloader.resolver.resolve(&downloader.client, "https://parti.com/video/42").unwrap();
+    assert_eq!(info.video_id, "42");
+}
+
+#[test]
+fn bearer_token_is_not_sent_to_other_hosts() {
+    let cdn = common::serve(common::vod_routes().refuse("Authorization"));
+    let video = format!(
+        r#"{{"event_title": "Elsewhere", "event_thumbnail": "{cdn}/thumbs/42.jpg",
+            "livestream_recording": "{cdn}/vod/42/master.m3u8"}}"#
+    );
+    let base = common::serve(
+        common::Routes::default().ok(API_PATH, video).protect(API_PATH, "Authorization: Bearer secret"),
+    );
+    let mut downloader = common::downloader(&base);
+    downloader.set_http(with_token()).unwrap();
+    assert!(!downloader.client.sends_token_to(&format!("{cdn}/vod/42/master.m3u8")));
+    let preview = downloader.preview("https://parti.com/video/42").unwrap();
+    assert_eq!(preview.thumbnail.as_deref(), Some(&b"thumbnail"[..]));
+
+    let (events, _receiver) = std::sync::mpsc::channel();
+    let options = DownloadOptions {
+        download_folder: Some(common::output_dir("token_hosts").display().to_string()),
+        resolved: Some(preview.info),
+        ..Default::default()
+    };
+    let result = downloader.download("https://parti.com/video/42", &options, &DownloadControl::default(), &events);
+    assert!(result.is_ok(), "{result:?}");
+
+    // The CDN does turn the token away once it is trusted.
+    downloader.client.trust(&cdn);
+    let status = downloader.client.get(format!("{cdn}/vod/42/master.m3u8")).send().unwrap().status();
+    assert_eq!(status.as_u16(), 400);
+}
+
+#[test]
+fn bearer_token_is_sent_to_parti_subdomains_only() {
+    let client = build_client(&with_token()).unwrap();
+    assert!(client.sends_token_to("https://api.parti.com/parti_v2/"));
+    assert!(client.sends_token_to("https://parti.com/video/42"));
+    assert!(!client.sends_token_to("https://notparti.com/video/42"));
+    assert!(!client.sends_token_to("https://cdn.example.com/vod/42/master.m3u8"));
+}
+
+#[test]
+fn cookies_file_is_sent() {
+    let base = common::serve(common::vod_routes().protect(API_PATH, "Cookie: session=abc"));
+    let cookies = common::output_dir("cookies").join("cookies.txt");
+    std::fs::write(
+        &cookies,
+        "# Netscape HTTP Cookie File\n\
+         #HttpOnly_127.0.0.1\tFALSE\t/\tFALSE\t0\tsession\tabc\n\
+         127.0.0.1\tFALSE\t/\tFALSE\t1\tstale\tgone\n",
+    )
+    .unwrap();
+    let auth = AuthSettings { cookies_file: cookies.display().to_string(), ..Default::default() };
+    let info = common::resolver(&base).resolve(&build_client(&with_auth(auth)).unwrap(), "https://parti.com/video/42");
+    assert_eq!(info.unwrap().video_id, "42");
+}
+
+#[test]
+fn saved_settings_are_private() {
+    let path = common::output_dir("network_save").join("network.json");
+    std::fs::write(&path, "{}").unwrap();
+    let settings = with_token();
+    settings.save_to(&path).unwrap();
+    let saved: HttpSettings = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved, settings);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn rejects_files_without_cookies() {
+    let path = common::output_dir("no_cookies").join("cookies.txt");
+    std::fs::write(&path, "session=abc\n").unwrap();
+    let auth = AuthSettings { cookies_file: path.display().to_string(), ..Default::default() };
+    assert!(matches!(build_client(&with_auth(auth)), Err(Error::Settings(_))));
+}
+
+#[test]
+fn forbidden_playlist_needs_credentials() {
+    let base = common::serve(common::vod_routes().status("/vod/42/master.m3u8", 403));
+    let (events, _receiver) = std::sync::mpsc::channel();
+    let options = DownloadOptions {
+        download_folder: Some(common::output_dir("forbidden").display().to_string()),
+        ..Default::default()
+    };
+    let result = common::downloader(&base).download(
+        "https://parti.com/video/42",
+        &options,
+        &DownloadControl::default(),
+        &events,
+    );
+    assert!(matches!(result, Err(Error::AuthRequired { status: 403 })), "{result:?}");
+}
+
+#[test]
+fn custom_headers_are_sent() {
+    let routes = common::vod_routes()
+        .protect(API_PATH, "User-Agent: test-agent/2.0")
+        .protect("/vod/42/master.m3u8", "X-Test: yes");
+    let base = common::serve(routes.protect("/vod/42/720p/playlist.m3u8", "Referer: https://parti.com/"));
+    let settings = HttpSettings {
+        user_agent: "test-agent/2.0".to_string(),
+        headers: "X-Test: yes\n".to_string(),
+        referer: "https://parti.com/".to_string(),
+        ..Default::default()
+    };
+    let client = build_client(&settings).unwrap();
+    common::resolver(&base).resolve(&client, "https://parti.com/video/42").unwrap();
+    for path in ["/vod/42/master.m3u8", "/vod/42/720p/playlist.m3u8"] {
+        assert!(client.get(format!("{base}{path}")).send().unwrap().status().is_success(), "{path}");
+    }
+}
+
+#[test]
+fn rejects_malformed_headers() {
+    let settings = HttpSettings { headers: "X-Test yes".to_string(), ..Default::default() };
+    assert!(matches!(build_client(&settings), Err(Error::Settings(_))));
+}
+
+#[test]
+fn stalled_requests_time_out() {
+    // Accepts connections but never answers.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/stalled", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let _open: Vec<_> = listener.incoming().collect();
+    });
+    let client = build_client(&HttpSettings { read_timeout: 1, ..Default::default() }).unwrap();
+    let started = std::time::Instant::now();
+    let error = Error::from(client.get(&url).send().unwrap_err());
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    assert!(error.is_retryable());
+}
+
+#[test]
+fn body_that_stalls_is_retryable() {
+    // Answers with the start of a body and then goes quiet.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/stalled", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let mut open = Vec::new();
+        for mut stream in listener.incoming().flatten() {
+            std::io::Read::read(&mut stream, &mut [0; 4096]).ok();
+            std::io::Write::write_all(&mut stream, b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\nstart").ok();
+            open.push(stream);
+        }
+    });
+    let client = build_client(&HttpSettings { read_timeout: 1, ..Default::default() }).unwrap();
+    let mut response = client.get(&url).send().unwrap();
+    let error = Error::from(std::io::Read::read_to_end(&mut response, &mut Vec::new()).unwrap_err());
+    assert!(error.is_retryable(), "{error:?}");
+}
+
+#[test]
+fn only_network_trouble_and_server_errors_are_retryable() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let refused = format!("http://{}/", listener.local_addr().unwrap());
+    drop(listener);
+    assert!(Error::from(http_client().get(&refused).send().unwrap_err()).is_retryable());
+
+    let base = common::serve(common::Routes::default().status("/busy", 503).status("/limited", 429));
+    let status_error = |path: &str| {
+        let response = http_client().get(format!("{base}{path}")).send().unwrap();
+        Error::from(response.error_for_status().unwrap_err())
+    };
+    assert!(status_error("/busy").is_retryable());
+    assert!(status_error("/limited").is_retryable());
+    assert!(!status_error("/missing").is_retryable());
+
+    assert!(!Error::from(std::io::Error::from(std::io::ErrorKind::StorageFull)).is_retryable());
+    assert!(!Error::EmptyVariant.is_retryable());
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use parti_vod_downloader::playlist::Quality;
+use parti_vod_downloader::{parse_section, DownloadControl, DownloadEvent, DownloadOptions, Downloader, HlsResolver};
+
+const URL: &str = "https://parti.com/video/42";
+
+#[test]
+fn preview_shows_details_and_estimated_size() {
+    let base = common::serve(common::vod_routes());
+    let preview = common::downloader(&base).preview(URL).unwrap();
+    assert_eq!(preview.info.title, "Test Stream: Day 1");
+    assert_eq!(preview.info.channel.as_deref(), Some("tester"));
+    assert_eq!(preview.thumbnail.as_deref(), Some(&b"thumbnail"[..]));
+    assert_eq!(preview.duration, 40.0);
+    assert_eq!(preview.qualities().len(), 1);
+    // 2 Mbit/s for 40 s.
+    assert_eq!(preview.estimated_size(None, "mp4", None), Some(10_000_000));
+    let section = parse_section("0:10-0:30").unwrap();
+    assert_eq!(preview.estimated_size(None, "mp4", Some(&section)), Some(5_000_000));
+}
+
+#[test]
+fn preview_lists_qualities_tallest_first() {
+    let base = common::serve(
+        common::vod_routes()
+            .ok("/shows/3/master", common::fixture("hls/master.m3u8"))
+            .ok("/shows/3/low/index.m3u8", common::fixture("hls/media.m3u8"))
+            .ok("/shows/3/high/index.m3u8", common::fixture("vod/42/720p/playlist.m3u8")),
+    );
+    let preview = Downloader::with_resolver(HlsResolver).preview(&format!("{base}/shows/3/master")).unwrap();
+    let heights: Vec<_> = preview.qualities().iter().map(|v| v.height).collect();
+    assert_eq!(heights, [Some(720), Some(360)]);
+    assert_eq!(preview.thumbnail, None);
+    let best = preview.estimated_size(Some(Quality::Best), "mp4", None);
+    assert!(best > preview.estimated_size(Some(Quality::Worst), "mp4", None));
+}
+
+#[test]
+fn confirmed_preview_is_downloaded_without_asking_the_api_again() {
+    let base = common::serve(common::vod_routes());
+    let preview = common::downloader(&base).preview(URL).unwrap();
+    let base_without_api =
+        common::serve(common::vod_routes().remove("/parti_v2/profile/get_livestream_channel_info/recent/42"));
+    let dir = common::output_dir("preview_confirmed");
+    let options = DownloadOptions {
+        download_folder: Some(dir.to_string_lossy().into_owned()),
+        resolved: Some(preview.info),
+        ..Default::default()
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    common::downloader(&base_without_api)
+        .download(URL, &options, &DownloadControl::default(), &tx)
+        .unwrap();
+    assert!(matches!(rx.try_iter().last(), Some(DownloadEvent::Finished { .. })));
+}
+
+#[test]
+fn audio_only_size_comes_from_the_audio_playlist() {
+    let master = "#EXTM3U\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=2000000,RESOLUTION=1280x720\n720p/playlist.m3u8\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=128000\naudio/playlist.m3u8\n";
+    let base = common::serve(common::vod_routes().ok("/vod/42/master.m3u8", master));
+    let preview = common::downloader(&base).preview(URL).unwrap();
+    // 128 kbit/s for 40 s, not the video's 2 Mbit/s.
+    assert_eq!(preview.estimated_size(None, "mp3", None), Some(640_000));
+    assert_eq!(preview.estimated_size(Some(Quality::Best), "mp4", None), Some(10_000_000));
+
+    // An audio rendition advertises no bandwidth of its own.
+    let master = "#EXTM3U\n\
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"English\",DEFAULT=YES,URI=\"audio/en.m3u8\"\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=2000000,AUDIO=\"aud\"\n720p/playlist.m3u8\n";
+    let base = common::serve(common::vod_routes().ok("/vod/42/master.m3u8", master));
+    let preview = common::downloader(&base).preview(URL).unwrap();
+    assert_eq!(preview.estimated_size(None, "wav", None), None);
+    assert_eq!(preview.estimated_size(None, "mp4", None), Some(10_000_000));
+}
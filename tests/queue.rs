@@ -0,0 +1,212 @@
+mod common;
+
+use common::Routes;
+use parti_vod_downloader::{DownloadEvent, DownloadOptions, DownloadQueue, QueueState};
+
+const URL: &str = "https://parti.com/video/42";
+
+fn options(test: &str) -> DownloadOptions {
+    let dir = common::output_dir(test);
+    DownloadOptions {
+        output_format: "ts".to_string(),
+        download_folder: Some(dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    }
+}
+
+/// A queue of `urls` against a mock server with `routes`.
+fn queue(test: &str, routes: Routes, concurrency: usize, urls: &[&str]) -> DownloadQueue {
+    let base = common::serve(routes);
+    let mut queue = DownloadQueue::with_downloader(concurrency, common::downloader(&base));
+    let options = options(test);
+    for url in urls {
+        queue.add(url.to_string(), options.clone());
+    }
+    queue
+}
+
+fn run(queue: &mut DownloadQueue) {
+    while queue.is_active() {
+        queue.tick();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    queue.tick();
+}
+
+fn states(queue: &DownloadQueue) -> Vec<QueueState> {
+    queue.items.iter().map(|item| item.state).collect()
+}
+
+#[test]
+fn runs_no_more_than_the_concurrency_at_once() {
+    let mut queue = queue("queue_concurrency", common::vod_routes(), 2, &[URL, URL, URL]);
+    queue.tick();
+    assert_eq!(states(&queue), [QueueState::Running, QueueState::Running, QueueState::Queued]);
+
+    // Lowering the limit does not stop running downloads, but nothing new starts until they are done.
+    queue.set_concurrency(1);
+    while queue.items[..2].iter().any(|item| item.state == QueueState::Running) {
+        queue.tick();
+        assert!(queue.items[2].state == QueueState::Queued || queue.running() == 1);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    assert_eq!(states(&queue), [QueueState::Done, QueueState::Done, QueueState::Running]);
+    run(&mut queue);
+    assert_eq!(states(&queue), [QueueState::Done; 3]);
+}
+
+#[test]
+fn items_are_moved_to_the_given_position() {
+    let mut queue = queue("queue_move", common::vod_routes(), 1, &[URL, URL, URL]);
+    let ids = |queue: &DownloadQueue| queue.items.iter().map(|item| item.id).collect::<Vec<_>>();
+    queue.move_item(0, 2);
+    assert_eq!(ids(&queue), [2, 3, 1]);
+    queue.move_item(2, 0);
+    assert_eq!(ids(&queue), [1, 2, 3]);
+    queue.move_item(1, 3);
+    assert_eq!(ids(&queue), [1, 2, 3], "out of range moves are ignored");
+
+    // The scheduler starts items in their new order.
+    queue.move_item(2, 0);
+    queue.tick();
+    assert_eq!(queue.items[0].id, 3);
+    assert_eq!(states(&queue), [QueueState::Running, QueueState::Queued, QueueState::Queued]);
+    run(&mut queue);
+}
+
+#[test]
+fn held_items_wait_until_released() {
+    let mut queue = queue("queue_hold", common::vod_routes(), 2, &[URL, URL]);
+    queue.hold(1);
+    run(&mut queue);
+    assert_eq!(states(&queue), [QueueState::Held, QueueState::Done]);
+    assert_eq!(queue.items[0].progress.status, "On hold");
+
+    queue.hold(2);
+    assert_eq!(queue.items[1].state, QueueState::Done, "only queued items can be held");
+    queue.release(1);
+    assert_eq!(queue.items[0].state, QueueState::Queued);
+    run(&mut queue);
+    assert_eq!(states(&queue), [QueueState::Done, QueueState::Done]);
+}
+
+#[test]
+fn retry_requeues_only_failed_items() {
+    // Video 7 does not exist on the mock server.
+    let urls = [URL, "https://parti.com/video/7", URL];
+    let mut queue = queue("queue_retry", common::vod_routes(), 1, &urls);
+    queue.cancel(3);
+    run(&mut queue);
+    assert_eq!(states(&queue), [QueueState::Done, QueueState::Failed, QueueState::Cancelled]);
+
+    queue.retry_failed();
+    assert_eq!(states(&queue), [QueueState::Done, QueueState::Queued, QueueState::Cancelled]);
+    assert_eq!(queue.items[1].progress.status, "Queued");
+    run(&mut queue);
+    assert_eq!(states(&queue), [QueueState::Done, QueueState::Failed, QueueState::Cancelled]);
+}
+
+#[test]
+fn saved_queue_is_restored() {
+    let base = common::serve(common::vod_routes().remove("/vod/42/720p/seg2.ts"));
+    let path = common::output_dir("queue_saved_state").join("queue.json");
+    let mut queue = DownloadQueue::open(path.clone());
+    assert!(queue.items.is_empty());
+    queue.downloader = common::downloader(&base);
+    queue.set_concurrency(1);
+    queue.set_rate_limit(Some(1_000_000));
+    let options = options("queue_saved");
+    for _ in 0..4 {
+        queue.add(URL.to_string(), options.clone());
+    }
+    queue.hold(3);
+    queue.cancel(4);
+    while !queue.items[0].state.is_finished() {
+        queue.tick();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    // The tick that saw the first item fail started the second.
+    assert_eq!(
+        states(&queue),
+        [QueueState::Failed, QueueState::Running, QueueState::Held, QueueState::Cancelled]
+    );
+    queue.save();
+    queue.stop_all();
+
+    let restored = DownloadQueue::open(path);
+    assert_eq!(restored.concurrency, 1);
+    assert_eq!(restored.downloader.limiter.limit(), Some(1_000_000));
+    // A download that was running when the app closed is queued again.
+    assert_eq!(
+        states(&restored),
+        [QueueState::Failed, QueueState::Queued, QueueState::Held, QueueState::Cancelled]
+    );
+    assert!(restored.items.iter().all(|item| item.url == URL));
+    let failed = &restored.items[0];
+    assert_eq!(failed.progress.status, queue.items[0].progress.status);
+    assert_eq!(failed.progress.fraction, 1.0);
+    // The failed download keeps the segments it had for a retry.
+    assert_eq!(failed.control.checkpoint().unwrap().next_segment, 2);
+}
+
+#[test]
+fn paused_item_gives_its_slot_to_the_next_one() {
+    let mut queue = queue("queue_pause", common::vod_routes(), 1, &[URL, URL]);
+    // Slow enough that the pause lands well before the last segment.
+    queue.set_rate_limit(Some(4000));
+    while queue.items[0].control.checkpoint().is_none() {
+        queue.tick();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    queue.pause(1);
+    while queue.items[0].state == QueueState::Running {
+        queue.tick();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    assert_eq!(states(&queue), [QueueState::Held, QueueState::Running]);
+    assert_eq!(queue.items[0].progress.status, "Paused");
+    let next_segment = queue.items[0].control.checkpoint().unwrap().next_segment;
+    assert!((1..4).contains(&next_segment), "{next_segment}");
+    assert!(queue.history.entries.is_empty(), "a pause is not the end of a download");
+
+    queue.set_rate_limit(None);
+    queue.resume(1);
+    assert_eq!(queue.items[0].state, QueueState::Queued);
+    run(&mut queue);
+    assert_eq!(states(&queue), [QueueState::Done, QueueState::Done]);
+    // The resumed download continued into the file it started.
+    let Some(DownloadEvent::Finished { paths, .. }) = &queue.items[0].progress.outcome else {
+        panic!("expected Finished, got {:?}", queue.items[0].progress.outcome);
+    };
+    let expected: Vec<u8> = (0..4).flat_map(common::segment).collect();
+    assert_eq!(paths[0].file_name().unwrap(), "Test_Stream_Day_1_2023-11-14.ts");
+    assert_eq!(std::fs::read(&paths[0]).unwrap(), expected);
+}
+
+#[test]
+fn cancelling_one_download_leaves_the_other_running() {
+    let mut queue = queue("queue_cancel_one", common::vod_routes(), 2, &[URL, URL]);
+    // Slow enough that both are still downloading when one is cancelled.
+    queue.set_rate_limit(Some(4000));
+    while queue.items.iter().any(|item| item.control.checkpoint().is_none()) {
+        queue.tick();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    assert_eq!(states(&queue), [QueueState::Running, QueueState::Running]);
+    queue.cancel(1);
+    while queue.items[0].state == QueueState::Running {
+        queue.tick();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    assert_eq!(states(&queue), [QueueState::Cancelled, QueueState::Running]);
+    assert!(!queue.items[1].control.is_cancelled());
+
+    queue.set_rate_limit(None);
+    run(&mut queue);
+    assert_eq!(states(&queue), [QueueState::Cancelled, QueueState::Done]);
+    let expected: Vec<u8> = (0..4).flat_map(common::segment).collect();
+    let Some(DownloadEvent::Finished { paths, .. }) = &queue.items[1].progress.outcome else {
+        panic!("expected Finished, got {:?}", queue.items[1].progress.outcome);
+    };
+    assert_eq!(std::fs::read(&paths[0]).unwrap(), expected);
+}
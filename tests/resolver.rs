@@ -0,0 +1,171 @@
+mod common;
+
+use parti_vod_downloader::resolver::{find_video_urls, parse_input, Target};
+use parti_vod_downloader::{http_client, Error, HlsResolver, Resolver};
+
+#[test]
+fn resolves_metadata_and_relative_playlist() {
+    let base = common::serve(common::vod_routes());
+    let info = common::resolver(&base)
+        .resolve(&http_client(), "https://parti.com/video/42")
+        .unwrap();
+    assert_eq!(info.video_id, "42");
+    assert_eq!(info.title, "Test Stream: Day 1");
+    assert_eq!(info.date, "2023-11-14");
+    assert_eq!(info.start_ts, 1700000000);
+    assert_eq!(info.playlist_url, format!("{base}/vod/42/master.m3u8"));
+    assert_eq!(info.channel.as_deref(), Some("tester"));
+    assert_eq!(info.thumbnail_url, Some(format!("{base}/thumbs/42.jpg")));
+}
+
+#[test]
+fn chat_url_uses_the_api_base() {
+    let base = common::serve(common::vod_routes());
+    let resolver = common::resolver(&base);
+    let info = resolver.resolve(&http_client(), "https://parti.com/video/42").unwrap();
+    assert_eq!(
+        resolver.chat_url(&info).unwrap(),
+        format!("{base}/parti_v2/profile/get_livestream_chat_history/42")
+    );
+}
+
+#[test]
+fn hls_resolver_rejects_non_http_input_as_a_playlist_url() {
+    let err = HlsResolver.resolve(&http_client(), "ftp://example.com/show.m3u8").unwrap_err();
+    assert!(matches!(err, Error::InvalidPlaylistUrl(_)), "{err:?}");
+    assert_eq!(err.to_string(), "Not an HLS playlist URL: 'ftp://example.com/show.m3u8': expected an http(s) URL");
+}
+
+#[test]
+fn rejects_urls_without_a_video_id() {
+    let base = common::serve(common::vod_routes());
+    let err = common::resolver(&base)
+        .resolve(&http_client(), "https://parti.com/creator")
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidUrl(_)), "{err:?}");
+}
+
+#[test]
+fn reports_api_error_status() {
+    let routes = common::vod_routes().status("/parti_v2/profile/get_livestream_channel_info/recent/42", 503);
+    let base = common::serve(routes);
+    let err = common::resolver(&base)
+        .resolve(&http_client(), "https://parti.com/video/42")
+        .unwrap_err();
+    assert!(matches!(err, Error::ApiHttp { status: 503 }), "{err:?}");
+    assert!(err.is_retryable());
+}
+
+#[test]
+fn reports_missing_playlist_field() {
+    let routes = common::vod_routes().ok(
+        "/parti_v2/profile/get_livestream_channel_info/recent/42",
+        common::fixture("video_no_playlist.json"),
+    );
+    let base = common::serve(routes);
+    let err = common::resolver(&base)
+        .resolve(&http_client(), "https://parti.com/video/42")
+        .unwrap_err();
+    assert!(matches!(err, Error::NoPlaylistField), "{err:?}");
+}
+
+#[test]
+fn normalizes_video_inputs() {
+    for input in [
+        "42",
+        " https://parti.com/video/42 ",
+        "https://www.parti.com/somecreator/video/42",
+        "parti.com/video/42?share=abc123&utm_source=x#t=10",
+        "http://parti.com/video/42/",
+    ] {
+        assert_eq!(parse_input(input).unwrap(), Target::Video("42".to_string()), "{input}");
+    }
+    assert_eq!(
+        parse_input("https://cdn.example.com/vod/42/master.m3u8?token=x").unwrap(),
+        Target::Playlist("https://cdn.example.com/vod/42/master.m3u8?token=x".to_string())
+    );
+}
+
+#[test]
+fn explains_rejected_inputs() {
+    for (input, reason) in [
+        ("", "nothing entered"),
+        ("https://parti.com/video/abc", "'abc' is not a number"),
+        ("https://parti.com/video/", "video ID after /video/ is missing"),
+        ("https://example.com/video/42", "not a parti.com address"),
+        ("https://parti.com/", "home page"),
+        ("https://parti.com/about", "no /video/<id>"),
+        ("https://parti.com/somecreator", "no /video/<id>"),
+        ("https://parti.com/explore/videos", "no /video/<id>"),
+        ("ftp://parti.com/video/42", "only http(s)"),
+    ] {
+        let err = parse_input(input).unwrap_err();
+        assert!(matches!(err, Error::InvalidUrl(_)), "{input}: {err:?}");
+        assert!(err.to_string().contains(reason), "{input}: {err}");
+    }
+}
+
+#[test]
+fn playlist_urls_skip_the_api() {
+    let base = common::serve(common::vod_routes().remove("/parti_v2/profile/get_livestream_channel_info/recent/42"));
+    let url = format!("{base}/vod/42/master.m3u8");
+    let info = common::resolver(&base).resolve(&http_client(), &url).unwrap();
+    assert_eq!(info.video_id, "42");
+    assert_eq!(info.playlist_url, url);
+}
+
+#[test]
+#[cfg(not(feature = "channels"))]
+fn channels_need_the_feature() {
+    let err = parse_input("https://parti.com/somecreator/videos").unwrap_err();
+    assert!(err.to_string().contains("`channels` feature"), "{err}");
+}
+
+#[test]
+#[cfg(feature = "channels")]
+fn channels_expand_to_their_recordings() {
+    let routes = common::vod_routes()
+        .ok("/parti_v2/profile/get_livestream_recordings/somecreator", common::fixture("channel.json"));
+    let base = common::serve(routes);
+    let resolver = common::resolver(&base);
+    assert_eq!(
+        parse_input("https://parti.com/somecreator/videos").unwrap(),
+        Target::Channel("somecreator".to_string())
+    );
+    assert_eq!(
+        resolver.expand(&http_client(), "https://parti.com/somecreator/videos").unwrap(),
+        ["https://parti.com/video/43", "https://parti.com/video/42"]
+    );
+    assert_eq!(resolver.expand(&http_client(), "42").unwrap(), ["42"]);
+}
+
+#[test]
+#[cfg(feature = "channels")]
+fn members_only_channel_needs_credentials() {
+    let routes = common::vod_routes()
+        .ok("/parti_v2/profile/get_livestream_recordings/somecreator", common::fixture("channel.json"))
+        .protect("/parti_v2/profile/get_livestream_recordings/somecreator", "Authorization: Bearer secret");
+    let base = common::serve(routes.status("/parti_v2/profile/get_livestream_recordings/gone", 404));
+    let resolver = common::resolver(&base);
+    let err = resolver.expand(&http_client(), "https://parti.com/somecreator/videos").unwrap_err();
+    assert!(matches!(err, Error::AuthRequired { status: 401 }), "{err:?}");
+    let err = resolver.expand(&http_client(), "https://parti.com/gone/videos").unwrap_err();
+    assert!(matches!(err, Error::ApiHttp { status: 404 }), "{err:?}");
+}
+
+#[test]
+fn finds_video_urls_in_text() {
+    let text = "Watch (https://parti.com/somecreator/video/42) or https://parti.com/video/43.\n\
+                Again: https://parti.com/video/43, the channel https://parti.com/somecreator/videos, \
+                <https://cdn.example.com/live/master.m3u8> and 12345\n\
+                [InternetShortcut]\nURL=https://parti.com/video/44";
+    assert_eq!(
+        find_video_urls(text),
+        [
+            "https://parti.com/somecreator/video/42",
+            "https://parti.com/video/43",
+            "https://cdn.example.com/live/master.m3u8",
+            "https://parti.com/video/44"
+        ]
+    );
+}